@@ -1,7 +1,8 @@
 use darling::{util::PathList, FromDeriveInput};
 use proc_macro2::TokenStream;
-use quote::quote;
-use syn::DeriveInput;
+use proc_macro_error::{abort, abort_call_site};
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields};
 
 #[derive(FromDeriveInput, Default)]
 #[darling(default, attributes(store))]
@@ -9,83 +10,285 @@ struct Opts {
     storage: Option<String>,
     storage_tab_sync: bool,
     listener: PathList,
+    hydrate: bool,
+    derived: bool,
+    snapshot: bool,
+    #[darling(multiple)]
+    middleware: Vec<syn::Path>,
+    drop_when_unused: bool,
+    on_drop: Option<syn::Path>,
+    fields: bool,
+    fields_name: Option<syn::Path>,
+    name: Option<String>,
 }
 
 pub(crate) fn derive(input: DeriveInput) -> TokenStream {
     let opts = Opts::from_derive_input(&input).expect("Invalid options");
+    if opts.derived {
+        // Derived stores are computed from their sources, so these would be ignored.
+        let conflicting = [
+            ("storage", opts.storage.is_some()),
+            ("storage_tab_sync", opts.storage_tab_sync),
+            ("listener", !opts.listener.is_empty()),
+            ("middleware", !opts.middleware.is_empty()),
+            ("hydrate", opts.hydrate),
+            ("snapshot", opts.snapshot),
+        ];
+        for (name, set) in conflicting {
+            if set {
+                abort_call_site!("`derived` cannot be combined with `{}`.", name);
+            }
+        }
+    }
     let ident = input.ident;
+    let vis = input.vis;
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
+    let middleware: Vec<_> = opts
+        .middleware
+        .iter()
+        .map(|path| {
+            quote! {
+                ::yewdux::middleware::register_once::<Self, _>(cx, #path);
+            }
+        })
+        .collect();
+
     let extra_listeners: Vec<_> = opts
         .listener
         .iter()
         .map(|path| {
             quote! {
-                ::yewdux::listener::init_listener(
+                ::yewdux::listener::init_listener_with_context(
+                    cx,
                     #path
                 );
             }
         })
         .collect();
 
-    let impl_ = match opts.storage {
-        Some(storage) => {
-            let area = match storage.as_ref() {
-                "local" => quote! { ::yewdux::storage::Area::Local },
-                "session" => quote! { ::yewdux::storage::Area::Session },
-                x => panic!(
-                    "'{}' is not a valid option. Must be 'local' or 'session'.",
-                    x
-                ),
-            };
-
-            let sync = if opts.storage_tab_sync {
-                quote! {
-                    if let Err(err) = ::yewdux::storage::init_tab_sync::<Self>(#area) {
-                        ::yewdux::log::error!("Unable to init tab sync for storage: {:?}", err);
+    // Register first, then take the server's state before any other initialization.
+    let (hydrate_register, hydrate_load) = if opts.hydrate {
+        (
+            quote! {
+                ::yewdux::hydration::register::<Self>();
+            },
+            quote! {
+                match ::yewdux::hydration::load() {
+                    Ok(Some(val)) => return val,
+                    Ok(None) => {}
+                    Err(err) => {
+                        ::yewdux::log::error!("Error loading hydration state: {:?}", err);
                     }
                 }
-            } else {
-                quote!()
-            };
+            },
+        )
+    } else {
+        (quote!(), quote!())
+    };
 
-            quote! {
-                #[cfg(target_arch = "wasm32")]
-                fn new() -> Self {
-                    ::yewdux::listener::init_listener(
-                        ::yewdux::storage::StorageListener::<Self>::new(#area)
-                    );
-                    #(#extra_listeners)*
+    let snapshot_register = if opts.snapshot {
+        quote! {
+            ::yewdux::registry::register::<Self>();
+        }
+    } else {
+        quote!()
+    };
 
-                    #sync
+    // Listeners are attached in the context the store is created in.
+    let new = quote! {
+        fn new() -> Self {
+            <Self as ::yewdux::store::Store>::new_with_context(&::yewdux::Context::global())
+        }
+    };
 
-                    match ::yewdux::storage::load(#area) {
-                        Ok(val) => val.unwrap_or_default(),
-                        Err(err) => {
-                            ::yewdux::log::error!("Error loading state from storage: {:?}", err);
+    let impl_ = if opts.derived {
+        quote! {
+            fn new() -> Self {
+                ::yewdux::derived::compute::<Self>(&::yewdux::Context::global())
+            }
 
-                            Default::default()
+            fn new_with_context(cx: &::yewdux::Context) -> Self {
+                ::yewdux::derived::new::<Self>(cx)
+            }
+        }
+    } else {
+        match opts.storage {
+            Some(storage) => {
+                let area = match storage.as_ref() {
+                    "local" => quote! { ::yewdux::storage::Area::Local },
+                    "session" => quote! { ::yewdux::storage::Area::Session },
+                    x => panic!(
+                        "'{}' is not a valid option. Must be 'local' or 'session'.",
+                        x
+                    ),
+                };
+
+                let sync = if opts.storage_tab_sync {
+                    quote! {
+                        if let Err(err) = ::yewdux::storage::init_tab_sync_with_context::<Self>(cx, #area) {
+                            ::yewdux::log::error!("Unable to init tab sync for storage: {:?}", err);
                         }
                     }
+                } else {
+                    quote!()
+                };
+
+                quote! {
+                    #new
+
+                    #[cfg(target_arch = "wasm32")]
+                    fn new_with_context(cx: &::yewdux::Context) -> Self {
+                        #hydrate_register
+                        #snapshot_register
+                        #(#middleware)*
+                        ::yewdux::listener::init_listener_with_context(
+                            cx,
+                            ::yewdux::storage::StorageListener::<Self>::new(#area)
+                        );
+                        #(#extra_listeners)*
+
+                        #sync
+
+                        #hydrate_load
+
+                        match ::yewdux::storage::load(#area) {
+                            Ok(val) => val.unwrap_or_default(),
+                            Err(err) => {
+                                ::yewdux::log::error!("Error loading state from storage: {:?}", err);
+
+                                Default::default()
+                            }
+                        }
+
+                    }
 
+                    #[cfg(not(target_arch = "wasm32"))]
+                    fn new_with_context(cx: &::yewdux::Context) -> Self {
+                        let _ = cx;
+                        #hydrate_register
+                        #snapshot_register
+                        #(#middleware)*
+                        #(#extra_listeners)*
+                        #hydrate_load
+                        Default::default()
+                    }
+
+                    fn reset(_cx: &::yewdux::Context) -> Self {
+                        #[cfg(target_arch = "wasm32")]
+                        if let Err(err) = ::yewdux::storage::clear::<Self>(#area) {
+                            ::yewdux::log::error!("Error clearing state from storage: {:?}", err);
+                        }
+
+                        Default::default()
+                    }
                 }
+            }
+            None => quote! {
+                #new
 
-                #[cfg(not(target_arch = "wasm32"))]
-                fn new() -> Self {
+                fn new_with_context(cx: &::yewdux::Context) -> Self {
+                    let _ = cx;
+                    #hydrate_register
+                    #snapshot_register
+                    #(#middleware)*
                     #(#extra_listeners)*
+                    #hydrate_load
+                    Default::default()
+                }
+
+                fn reset(_cx: &::yewdux::Context) -> Self {
                     Default::default()
                 }
+            },
+        }
+    };
+
+    let retain = if opts.drop_when_unused {
+        quote! {
+            fn retain(&self) -> bool {
+                false
             }
         }
-        None => quote! {
-            fn new() -> Self {
-                #(#extra_listeners)*
-                Default::default()
+    } else {
+        quote!()
+    };
+
+    let on_drop = match opts.on_drop {
+        Some(path) => quote! {
+            fn on_drop(&self) {
+                #path(self);
             }
         },
+        None => quote!(),
     };
 
+    let field_changes = if opts.fields {
+        let fields = match input.data {
+            Data::Struct(data) => match data.fields {
+                Fields::Named(fields) => fields.named,
+                fields => abort!(fields, "`fields` requires a struct with named fields."),
+            },
+            _ => abort!(ident, "`fields` requires a struct with named fields."),
+        };
+        if fields.len() > 64 {
+            abort!(fields[64], "`fields` supports up to 64 fields.");
+        }
+
+        let fields_ident = match &opts.fields_name {
+            Some(path) => match path.get_ident() {
+                Some(name) => name.clone(),
+                None => abort_call_site!("`fields_name` must be a plain name."),
+            },
+            None => format_ident!("{}Fields", ident),
+        };
+        let field_idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+        let indices = 0..field_idents.len() as u32;
+        let doc = format!("A mask for each field of [`{}`].", ident);
+
+        quote! {
+            #[doc = #doc]
+            #[derive(Clone, Copy, Debug)]
+            #vis struct #fields_ident {
+                #(pub #field_idents: ::yewdux::fields::FieldMask,)*
+            }
+
+            #[automatically_derived]
+            impl #impl_generics ::yewdux::fields::FieldChanges for #ident #ty_generics #where_clause {
+                type Fields = #fields_ident;
+
+                const FIELDS: Self::Fields = #fields_ident {
+                    #(#field_idents: ::yewdux::fields::FieldMask::field(#indices),)*
+                };
+
+                fn changed_fields(&self, old: &Self) -> ::yewdux::fields::FieldMask {
+                    let mut changed = ::yewdux::fields::FieldMask::NONE;
+                    #(
+                        if self.#field_idents != old.#field_idents {
+                            changed |= Self::FIELDS.#field_idents;
+                        }
+                    )*
+                    changed
+                }
+            }
+        }
+    } else {
+        if opts.fields_name.is_some() {
+            abort_call_site!("`fields_name` requires `fields`.");
+        }
+        quote!()
+    };
+
+    let name = opts.name.unwrap_or_else(|| ident.to_string());
+
     quote! {
+        #field_changes
+
+        #[automatically_derived]
+        impl #impl_generics ::yewdux::store::Named for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #name;
+        }
+
         #[automatically_derived]
         impl #impl_generics ::yewdux::store::Store for #ident #ty_generics #where_clause {
             #impl_
@@ -93,6 +296,9 @@ pub(crate) fn derive(input: DeriveInput) -> TokenStream {
             fn should_notify(&self, other: &Self) -> bool {
                 self != other
             }
+
+            #retain
+            #on_drop
         }
     }
 }
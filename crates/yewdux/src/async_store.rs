@@ -0,0 +1,406 @@
+//! Stores that load their initial state asynchronously.
+//!
+//! An [`AsyncStore`] starts out as [`Store::new`], and is replaced by the result of
+//! [`AsyncStore::init`] once it is loaded. Components can suspend until then with
+//! [`use_store_suspense`](crate::functional::use_store_suspense), so they never see the
+//! placeholder.
+//!
+//! Init runs once per context, no matter how many components wait for it. If it fails, the error
+//! is kept and returned to everyone waiting, until [`Dispatch::reload`] is called. Resetting the
+//! store, or dropping it when unused, unloads it, so it is loaded again on next use.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use yew::prelude::*;
+//! use yewdux::{async_store::AsyncStore, prelude::*};
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Settings {
+//!     theme: String,
+//! }
+//!
+//! #[yewdux::async_trait(?Send)]
+//! impl AsyncStore for Settings {
+//!     type Error = String;
+//!
+//!     async fn init() -> Result<Self, Self::Error> {
+//!         // Fetch settings here.
+//!         Ok(Self {
+//!             theme: "dark".into(),
+//!         })
+//!     }
+//! }
+//!
+//! #[function_component]
+//! fn Theme() -> HtmlResult {
+//!     let settings = match use_store_suspense::<Settings>()? {
+//!         Ok((settings, _)) => settings,
+//!         Err(err) => return Ok(html! { <p>{ format!("Unable to load settings: {err}") }</p> }),
+//!     };
+//!
+//!     Ok(html! { <p>{ &settings.theme }</p> })
+//! }
+//!
+//! #[function_component]
+//! fn App() -> Html {
+//!     html! {
+//!         <Suspense fallback={html! { <p>{ "Loading..." }</p> }}>
+//!             <Theme />
+//!         </Suspense>
+//!     }
+//! }
+//! ```
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+use async_trait::async_trait;
+
+use crate::{context::Context, dispatch::Dispatch, mrc::Mrc, store::Store};
+
+/// A store whose initial state is loaded asynchronously.
+#[async_trait(?Send)]
+pub trait AsyncStore: Store + Sized {
+    /// Error returned when loading fails.
+    type Error: 'static;
+
+    /// Load the initial state.
+    async fn init() -> Result<Self, Self::Error>;
+}
+
+/// How far along loading an [`AsyncStore`] is.
+#[derive(Debug)]
+pub enum LoadState<E> {
+    /// Loading hasn't started.
+    Idle,
+    /// Loading has started, but isn't done.
+    Loading,
+    /// The loaded state has been applied.
+    Ready,
+    /// Loading failed.
+    Failed(Rc<E>),
+}
+
+impl<E> Clone for LoadState<E> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Idle => Self::Idle,
+            Self::Loading => Self::Loading,
+            Self::Ready => Self::Ready,
+            Self::Failed(err) => Self::Failed(Rc::clone(err)),
+        }
+    }
+}
+
+/// The state and dispatch of a loaded store, or the error if loading failed. Returned by
+/// [`use_store_suspense`](crate::functional::use_store_suspense).
+pub type Loaded<S> = Result<(Rc<S>, Dispatch<S>), Rc<<S as AsyncStore>::Error>>;
+
+type Init<S> = Pin<Box<dyn Future<Output = Result<S, <S as AsyncStore>::Error>>>>;
+
+enum Status<S: AsyncStore> {
+    Idle,
+    Loading {
+        /// The init future, taken out while being polled.
+        init: Option<Init<S>>,
+        /// Everyone waiting for it.
+        wakers: Vec<Waker>,
+    },
+    Ready,
+    Failed(Rc<S::Error>),
+}
+
+impl<S: AsyncStore> Status<S> {
+    fn wake(&mut self) {
+        if let Status::Loading { wakers, .. } = self {
+            for waker in wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+struct Loading<S: AsyncStore>(Status<S>);
+impl<S: AsyncStore> Store for Mrc<Loading<S>> {
+    fn new() -> Self {
+        Loading(Status::Idle).into()
+    }
+
+    fn new_with_context(cx: &Context) -> Self {
+        // Once `S` is reset or dropped it's no longer loaded.
+        cx.on_reset::<S>(|cx| {
+            let mut status = std::mem::replace(&mut cx.loading::<S>().borrow_mut().0, Status::Idle);
+            status.wake();
+        });
+
+        <Self as Store>::new()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+/// Resolves once an [`AsyncStore`] is loaded. See [`Dispatch::load`].
+pub struct Load<S: AsyncStore> {
+    cx: Context,
+    _marker: PhantomData<S>,
+}
+
+impl<S: AsyncStore> Future for Load<S> {
+    type Output = Result<Rc<S>, Rc<S::Error>>;
+
+    fn poll(self: Pin<&mut Self>, task: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let loading = self.cx.loading::<S>();
+        let idle = matches!(loading.borrow().0, Status::Idle);
+        if idle {
+            // Reset or dropped since loading started, load again.
+            self.cx.load::<S>();
+        }
+
+        let mut init = match &mut loading.borrow_mut().0 {
+            Status::Idle => unreachable!("loading was started"),
+            Status::Ready => return Poll::Ready(Ok(self.cx.get::<S>())),
+            Status::Failed(err) => return Poll::Ready(Err(Rc::clone(err))),
+            Status::Loading { init, wakers } => {
+                if !wakers.iter().any(|waker| waker.will_wake(task.waker())) {
+                    wakers.push(task.waker().clone());
+                }
+
+                match init.take() {
+                    Some(init) => init,
+                    // Already being polled further up the stack.
+                    None => return Poll::Pending,
+                }
+            }
+        };
+
+        // Whoever polls drives the init. Don't hold the borrow, init may use other stores.
+        let result = match init.as_mut().poll(task) {
+            Poll::Ready(result) => result,
+            Poll::Pending => {
+                if let Status::Loading { init: slot, .. } = &mut loading.borrow_mut().0 {
+                    *slot = Some(init);
+                }
+                return Poll::Pending;
+            }
+        };
+
+        let mut status = match result {
+            Ok(state) => {
+                // Mark it ready first, so subscribers see it as loaded.
+                let status = std::mem::replace(&mut loading.borrow_mut().0, Status::Ready);
+                self.cx.reduce_named("init", move |_| Rc::new(state));
+                status
+            }
+            Err(err) => {
+                std::mem::replace(&mut loading.borrow_mut().0, Status::Failed(Rc::new(err)))
+            }
+        };
+        status.wake();
+
+        let result = match &loading.borrow().0 {
+            Status::Failed(err) => Err(Rc::clone(err)),
+            _ => Ok(self.cx.get::<S>()),
+        };
+
+        Poll::Ready(result)
+    }
+}
+
+impl<S: AsyncStore> Drop for Load<S> {
+    fn drop(&mut self) {
+        // If this was driving the init, let someone else take over.
+        self.cx.loading::<S>().borrow_mut().0.wake();
+    }
+}
+
+impl Context {
+    fn loading<S: AsyncStore>(&self) -> Mrc<Loading<S>> {
+        let entry = self.get_or_init_internal::<Mrc<Loading<S>>>();
+        let loading = entry.store.borrow().as_ref().clone();
+        loading
+    }
+
+    /// Start loading `S`, if it hasn't been already.
+    pub(crate) fn load<S: AsyncStore>(&self) -> Load<S> {
+        let loading = self.loading::<S>();
+        let mut loading = loading.borrow_mut();
+        if let Status::Idle = loading.0 {
+            loading.0 = Status::Loading {
+                init: Some(S::init()),
+                wakers: Vec::new(),
+            };
+        }
+
+        Load {
+            cx: self.clone(),
+            _marker: Default::default(),
+        }
+    }
+
+    /// Load `S` again, even if it was already loaded or failed.
+    pub(crate) fn reload<S: AsyncStore>(&self) -> Load<S> {
+        let mut status = std::mem::replace(&mut self.loading::<S>().borrow_mut().0, Status::Idle);
+        let load = self.load::<S>();
+        // Anyone waiting on the old init now waits on the new one.
+        status.wake();
+
+        load
+    }
+
+    pub(crate) fn load_state<S: AsyncStore>(&self) -> LoadState<S::Error> {
+        match &self.loading::<S>().borrow().0 {
+            Status::Idle => LoadState::Idle,
+            Status::Loading { .. } => LoadState::Loading,
+            Status::Ready => LoadState::Ready,
+            Status::Failed(err) => LoadState::Failed(Rc::clone(err)),
+        }
+    }
+}
+
+impl<S: AsyncStore> Dispatch<S> {
+    /// Start loading the store, if it hasn't been already. Resolves with the loaded state, or the
+    /// error if loading failed.
+    pub fn load(&self) -> Load<S> {
+        self.context().load()
+    }
+
+    /// Load the store again, even if it was already loaded or failed.
+    pub fn reload(&self) -> Load<S> {
+        self.context().reload()
+    }
+
+    /// How far along loading the store is.
+    pub fn load_state(&self) -> LoadState<S::Error> {
+        self.context().load_state::<S>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use crate::test_util::{isolated, TestState};
+
+    use super::*;
+
+    thread_local! {
+        static INITS: Cell<u32> = Default::default();
+        static RESULT: RefCell<Option<Result<u32, String>>> = Default::default();
+    }
+
+    /// Resolves with `RESULT` once it is set.
+    #[async_trait(?Send)]
+    impl AsyncStore for TestState {
+        type Error = String;
+
+        async fn init() -> Result<Self, Self::Error> {
+            INITS.with(|inits| inits.set(inits.get() + 1));
+            std::future::poll_fn(|_| match RESULT.with(|result| result.take()) {
+                Some(result) => Poll::Ready(result.map(TestState)),
+                None => Poll::Pending,
+            })
+            .await
+        }
+    }
+
+    fn resolve(result: Result<u32, &str>) {
+        RESULT.with(|slot| *slot.borrow_mut() = Some(result.map_err(Into::into)));
+    }
+
+    fn poll(load: &mut Load<TestState>) -> Poll<Result<Rc<TestState>, Rc<String>>> {
+        Pin::new(load).poll(&mut std::task::Context::from_waker(Waker::noop()))
+    }
+
+    fn setup() -> Dispatch<TestState> {
+        INITS.with(|inits| inits.set(0));
+        RESULT.with(|result| result.take());
+
+        isolated()
+    }
+
+    #[test]
+    fn load_applies_state() {
+        let dispatch = setup();
+        assert!(matches!(dispatch.load_state(), LoadState::Idle));
+
+        let mut load = dispatch.load();
+        assert!(poll(&mut load).is_pending());
+        assert!(matches!(dispatch.load_state(), LoadState::Loading));
+        assert!(dispatch.get().0 == 0);
+
+        resolve(Ok(1));
+        assert!(matches!(poll(&mut load), Poll::Ready(Ok(state)) if state.0 == 1));
+        assert!(matches!(dispatch.load_state(), LoadState::Ready));
+        assert!(dispatch.get().0 == 1);
+    }
+
+    #[test]
+    fn init_is_shared() {
+        let dispatch = setup();
+
+        let mut first = dispatch.load();
+        let mut second = dispatch.load();
+        assert!(poll(&mut first).is_pending());
+        assert!(poll(&mut second).is_pending());
+        // The one driving the init goes away.
+        drop(first);
+
+        resolve(Ok(1));
+        assert!(matches!(poll(&mut second), Poll::Ready(Ok(state)) if state.0 == 1));
+        assert!(INITS.with(Cell::get) == 1);
+
+        // Already loaded.
+        assert!(matches!(poll(&mut dispatch.load()), Poll::Ready(Ok(_))));
+        assert!(INITS.with(Cell::get) == 1);
+    }
+
+    #[test]
+    fn errors_are_surfaced() {
+        let dispatch = setup();
+
+        resolve(Err("offline"));
+        assert!(matches!(poll(&mut dispatch.load()), Poll::Ready(Err(err)) if *err == "offline"));
+        assert!(matches!(dispatch.load_state(), LoadState::Failed(_)));
+        // Failing is kept, until reloading.
+        assert!(matches!(poll(&mut dispatch.load()), Poll::Ready(Err(_))));
+
+        resolve(Ok(1));
+        assert!(matches!(poll(&mut dispatch.reload()), Poll::Ready(Ok(state)) if state.0 == 1));
+        assert!(INITS.with(Cell::get) == 2);
+    }
+
+    #[test]
+    fn reset_unloads_store() {
+        let dispatch = setup();
+        resolve(Ok(1));
+        assert!(poll(&mut dispatch.load()).is_ready());
+
+        dispatch.reset();
+
+        assert!(matches!(dispatch.load_state(), LoadState::Idle));
+        assert!(dispatch.get().0 == 0);
+    }
+
+    #[test]
+    fn load_restarts_after_reset() {
+        let dispatch = setup();
+        let mut load = dispatch.load();
+        assert!(poll(&mut load).is_pending());
+        assert!(dispatch.get().0 == 0);
+
+        dispatch.reset();
+        assert!(poll(&mut load).is_pending());
+        assert!(INITS.with(Cell::get) == 2);
+
+        resolve(Ok(1));
+        assert!(matches!(poll(&mut load), Poll::Ready(Ok(state)) if state.0 == 1));
+        assert!(matches!(dispatch.load_state(), LoadState::Ready));
+    }
+}
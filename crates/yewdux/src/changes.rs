@@ -0,0 +1,516 @@
+//! Subscriptions to single keys of a collection store.
+//!
+//! Every selector on a store runs whenever the store changes, even if it selects a part that
+//! didn't. For a store holding many rows, a reducer can instead report which keys it changed, and
+//! only the subscribers to those keys are woken.
+//!
+//! ```
+//! use std::{collections::HashMap, rc::Rc};
+//!
+//! use yew::prelude::*;
+//! use yewdux::{
+//!     changes::{use_selector_keyed, KeyedChanges},
+//!     prelude::*,
+//! };
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Rows {
+//!     rows: HashMap<u32, String>,
+//! }
+//!
+//! impl KeyedChanges for Rows {
+//!     type Key = u32;
+//! }
+//!
+//! #[derive(Properties, PartialEq)]
+//! struct Props {
+//!     id: u32,
+//! }
+//!
+//! #[function_component]
+//! fn Row(props: &Props) -> Html {
+//!     // Only runs when this row is marked as changed.
+//!     let text = use_selector_keyed(props.id, |rows: &Rows, id| rows.rows.get(id).cloned());
+//!     let onclick = {
+//!         let id = props.id;
+//!         Dispatch::<Rows>::new().reduce_mut_keyed_callback(move |rows, changes| {
+//!             rows.rows.insert(id, "Edited".into());
+//!             changes.mark(id);
+//!         })
+//!     };
+//!
+//!     html! {
+//!         <p {onclick}>{ text.as_deref().unwrap_or_default() }</p>
+//!     }
+//! }
+//! ```
+//!
+//! Changes that aren't reported, such as with [`Dispatch::reduce_mut`], are found with
+//! [`KeyedChanges::changed_keys`], which treats every key as changed by default.
+use std::{
+    any::type_name,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    rc::{Rc, Weak},
+};
+
+use slab::Slab;
+use yew::{functional::*, Callback};
+
+use crate::{
+    context::Context, dispatch::Dispatch, functional::use_selected, mrc::Mrc, store::Store,
+    subscriber::SubscriberId,
+};
+
+/// A store that can be subscribed to by key.
+pub trait KeyedChanges: Store {
+    type Key: Clone + Eq + Hash + 'static;
+
+    /// Keys that changed since `old`, for changes that weren't reported by the reducer. Every key
+    /// is treated as changed by default.
+    fn changed_keys(&self, _old: &Self) -> Changes<Self::Key> {
+        Changes::All
+    }
+}
+
+/// Keys changed by a reducer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Changes<K: Eq + Hash> {
+    /// Every key may have changed.
+    All,
+    /// Only these keys changed.
+    Keys(HashSet<K>),
+}
+
+impl<K: Eq + Hash> Changes<K> {
+    /// No keys changed.
+    pub fn none() -> Self {
+        Self::Keys(HashSet::new())
+    }
+
+    /// Mark `key` as changed.
+    pub fn mark(&mut self, key: K) {
+        if let Self::Keys(keys) = self {
+            keys.insert(key);
+        }
+    }
+
+    /// Mark every key as changed.
+    pub fn mark_all(&mut self) {
+        *self = Self::All;
+    }
+
+    /// Whether `key` changed.
+    pub fn contains(&self, key: &K) -> bool {
+        match self {
+            Self::All => true,
+            Self::Keys(keys) => keys.contains(key),
+        }
+    }
+
+    /// Whether no keys changed.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Self::Keys(keys) if keys.is_empty())
+    }
+
+    fn extend(&mut self, other: Self) {
+        match other {
+            Self::All => self.mark_all(),
+            Self::Keys(other) => {
+                if let Self::Keys(keys) = self {
+                    keys.extend(other);
+                }
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash> Default for Changes<K> {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl<K: Eq + Hash> FromIterator<K> for Changes<K> {
+    fn from_iter<T: IntoIterator<Item = K>>(iter: T) -> Self {
+        Self::Keys(iter.into_iter().collect())
+    }
+}
+
+type OnChange<S> = Rc<dyn Fn(Rc<S>)>;
+
+/// Keyed subscribers of `S`, and the changes reported since they were last notified.
+struct Index<S: KeyedChanges> {
+    subscribers: HashMap<S::Key, Slab<OnChange<S>>>,
+    /// Subscription to the store itself, while there are keyed subscribers.
+    subscription: Option<SubscriberId<S>>,
+    /// State as of the last notification.
+    last: Option<Rc<S>>,
+    /// Changes reported since then, and the state they lead to.
+    reported: Option<(Weak<S>, Changes<S::Key>)>,
+}
+
+impl<S: KeyedChanges> Index<S> {
+    fn report(&mut self, base: &Rc<S>, state: &Rc<S>, changes: Changes<S::Key>) {
+        if self.subscription.is_none() {
+            return;
+        }
+
+        match &mut self.reported {
+            // Follows the changes already reported.
+            Some((tip, reported)) if tip.as_ptr() == Rc::as_ptr(base) => {
+                reported.extend(changes);
+                *tip = Rc::downgrade(state);
+            }
+            _ => {
+                // Unless this follows what subscribers last saw, something else changed the state
+                // in between, and the reported changes are incomplete.
+                let follows_last = self
+                    .last
+                    .as_ref()
+                    .is_some_and(|last| Rc::ptr_eq(last, base));
+                self.reported = follows_last.then(|| (Rc::downgrade(state), changes));
+            }
+        }
+    }
+
+    fn changed(&mut self, state: &Rc<S>) -> Changes<S::Key> {
+        let changes = match (self.reported.take(), &self.last) {
+            (Some((tip, changes)), _) if tip.as_ptr() == Rc::as_ptr(state) => changes,
+            (_, Some(last)) => state.changed_keys(last),
+            (_, None) => Changes::All,
+        };
+        self.last = Some(Rc::clone(state));
+
+        changes
+    }
+}
+
+impl<S: KeyedChanges> Store for Mrc<Index<S>> {
+    fn new() -> Self {
+        Index::<S> {
+            subscribers: HashMap::new(),
+            subscription: None,
+            last: None,
+            reported: None,
+        }
+        .into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+fn notify<S: KeyedChanges>(index: &Mrc<Index<S>>, state: &Rc<S>) {
+    let on_changes: Vec<_> = {
+        let mut index = index.borrow_mut();
+        let subscribers = |slab: &Slab<OnChange<S>>| {
+            slab.iter()
+                .map(|(_, on_change)| Rc::clone(on_change))
+                .collect::<Vec<_>>()
+        };
+
+        match index.changed(state) {
+            Changes::All => index.subscribers.values().flat_map(subscribers).collect(),
+            Changes::Keys(keys) => keys
+                .iter()
+                .filter_map(|key| index.subscribers.get(key))
+                .flat_map(subscribers)
+                .collect(),
+        }
+    };
+
+    // Call outside of borrow, so subscribers can subscribe and unsubscribe.
+    for on_change in on_changes {
+        on_change(Rc::clone(state));
+    }
+}
+
+/// Points to a keyed subscriber. That subscriber is removed when this is dropped.
+pub struct KeyedSubscriberId<S: KeyedChanges> {
+    index: Mrc<Index<S>>,
+    key: S::Key,
+    id: usize,
+}
+
+impl<S: KeyedChanges> Drop for KeyedSubscriberId<S> {
+    fn drop(&mut self) {
+        let subscription = {
+            let mut index = self.index.borrow_mut();
+            if let Some(slab) = index.subscribers.get_mut(&self.key) {
+                slab.try_remove(self.id);
+                if slab.is_empty() {
+                    index.subscribers.remove(&self.key);
+                }
+            }
+
+            if index.subscribers.is_empty() {
+                index.last = None;
+                index.reported = None;
+                index.subscription.take()
+            } else {
+                None
+            }
+        };
+
+        // Unsubscribe outside of borrow, as it may drop the store.
+        drop(subscription);
+    }
+}
+
+impl Context {
+    fn keyed_index<S: KeyedChanges>(&self) -> Mrc<Index<S>> {
+        let entry = self.get_or_init_internal::<Mrc<Index<S>>>();
+        let index = entry.store.borrow().as_ref().clone();
+        index
+    }
+
+    /// Change state, reporting which keys changed.
+    pub(crate) fn reduce_keyed<S, F>(&self, f: F)
+    where
+        S: KeyedChanges,
+        F: FnOnce(Rc<S>) -> (Rc<S>, Changes<S::Key>),
+    {
+        self.assert_writable::<S>();
+
+        let index = self.keyed_index::<S>();
+        self.reduce_named(type_name::<F>(), |base: Rc<S>| {
+            let (state, changes) = f(Rc::clone(&base));
+            // Reported before committing, as subscribers are notified on commit.
+            index.borrow_mut().report(&base, &state, changes);
+            state
+        });
+    }
+
+    /// Call `on_change` whenever `key` is changed.
+    pub(crate) fn subscribe_keyed<S, F>(&self, key: S::Key, on_change: F) -> KeyedSubscriberId<S>
+    where
+        S: KeyedChanges,
+        F: Fn(Rc<S>) + 'static,
+    {
+        let index = self.keyed_index::<S>();
+        let subscription = index.borrow().subscription.is_none().then(|| {
+            // The context holds this subscription, so it mustn't keep it alive.
+            let cx = self.downgrade();
+            self.subscribe_silent(move |state: Rc<S>| {
+                if let Some(cx) = cx.upgrade() {
+                    notify(&cx.keyed_index::<S>(), &state);
+                }
+            })
+        });
+
+        let mut inner = index.borrow_mut();
+        if let Some(subscription) = subscription {
+            inner.subscription = Some(subscription);
+            inner.last = Some(self.get::<S>());
+        }
+        let id = inner
+            .subscribers
+            .entry(key.clone())
+            .or_default()
+            .insert(Rc::new(on_change));
+        drop(inner);
+
+        KeyedSubscriberId { index, key, id }
+    }
+}
+
+impl<S: KeyedChanges> Dispatch<S> {
+    /// Change state, returning which keys changed. Only subscribers to those keys are notified.
+    pub fn reduce_keyed<F>(&self, f: F)
+    where
+        F: FnOnce(Rc<S>) -> (Rc<S>, Changes<S::Key>),
+    {
+        self.context().reduce_keyed(f);
+    }
+
+    /// Change state using a mutable reference, marking which keys changed. Only subscribers to
+    /// those keys are notified.
+    pub fn reduce_mut_keyed<F, R>(&self, f: F) -> R
+    where
+        S: Clone,
+        F: FnOnce(&mut S, &mut Changes<S::Key>) -> R,
+    {
+        let mut result = None;
+
+        self.context().reduce_keyed(|mut state: Rc<S>| {
+            let mut changes = Changes::none();
+            result = Some(f(Rc::make_mut(&mut state), &mut changes));
+            (state, changes)
+        });
+
+        result.expect("result not initialized")
+    }
+
+    /// Like [`Self::reduce_mut_keyed`], but from a callback.
+    pub fn reduce_mut_keyed_callback<F, E>(&self, f: F) -> Callback<E>
+    where
+        S: Clone,
+        F: Fn(&mut S, &mut Changes<S::Key>) + 'static,
+        E: 'static,
+    {
+        let dispatch = self.clone();
+        Callback::from(move |_| dispatch.reduce_mut_keyed(&f))
+    }
+
+    /// Call `on_change` whenever `key` is changed, until the returned id is dropped.
+    pub fn subscribe_keyed<F>(&self, key: S::Key, on_change: F) -> KeyedSubscriberId<S>
+    where
+        F: Fn(Rc<S>) + 'static,
+    {
+        self.context().subscribe_keyed(key, on_change)
+    }
+}
+
+/// Similar to [`use_selector_with_deps`](crate::functional::use_selector_with_deps), but the
+/// selector only runs when `key` is changed. See the [changes](crate::changes) module.
+#[hook]
+pub fn use_selector_keyed<S, F, R>(key: S::Key, selector: F) -> Rc<R>
+where
+    S: KeyedChanges,
+    R: PartialEq + 'static,
+    F: Fn(&S, &S::Key) -> R + 'static,
+{
+    use_selected(
+        move |cx, key| Rc::new(selector(&cx.get::<S>(), key)),
+        |a, b| a == b,
+        key,
+        |cx, key, on_change| cx.subscribe_keyed(key.clone(), move |_: Rc<S>| on_change()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::test_util::isolated;
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Rows(HashMap<u32, u32>);
+    impl Store for Rows {
+        fn new() -> Self {
+            Self((0..3).map(|id| (id, 0)).collect())
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    impl KeyedChanges for Rows {
+        type Key = u32;
+
+        fn changed_keys(&self, old: &Self) -> Changes<u32> {
+            self.0
+                .iter()
+                .filter(|(id, value)| old.0.get(id) != Some(value))
+                .map(|(id, _)| *id)
+                .collect()
+        }
+    }
+
+    type Log = Rc<RefCell<Vec<u32>>>;
+
+    /// Subscribes to every row, logging which are notified.
+    fn setup() -> (Dispatch<Rows>, Log, Vec<KeyedSubscriberId<Rows>>) {
+        let dispatch = isolated::<Rows>();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let ids = (0..3)
+            .map(|id| {
+                let log = log.clone();
+                dispatch.subscribe_keyed(id, move |_| log.borrow_mut().push(id))
+            })
+            .collect();
+
+        (dispatch, log, ids)
+    }
+
+    fn notified(log: &RefCell<Vec<u32>>) -> Vec<u32> {
+        let mut notified = log.take();
+        notified.sort();
+        notified
+    }
+
+    fn set(id: u32, value: u32) -> impl FnOnce(&mut Rows, &mut Changes<u32>) {
+        move |rows, changes| {
+            rows.0.insert(id, value);
+            changes.mark(id);
+        }
+    }
+
+    #[test]
+    fn only_changed_keys_are_notified() {
+        let (dispatch, log, _ids) = setup();
+
+        dispatch.reduce_mut_keyed(set(1, 1));
+
+        assert_eq!(notified(&log), vec![1]);
+    }
+
+    #[test]
+    fn reported_changes_are_combined_in_batch() {
+        let (dispatch, log, _ids) = setup();
+
+        dispatch.batch(|dispatch| {
+            dispatch.reduce_mut_keyed(set(0, 1));
+            dispatch.reduce_mut_keyed(set(2, 1));
+        });
+
+        assert_eq!(notified(&log), vec![0, 2]);
+    }
+
+    #[test]
+    fn unreported_changes_use_changed_keys() {
+        let (dispatch, log, _ids) = setup();
+
+        dispatch.reduce_mut(|rows| {
+            rows.0.insert(2, 1);
+        });
+        assert_eq!(notified(&log), vec![2]);
+
+        // Reported changes can't be trusted once mixed with unreported ones.
+        dispatch.batch(|dispatch| {
+            dispatch.reduce_mut_keyed(|rows, _| {
+                rows.0.insert(0, 1);
+            });
+            dispatch.reduce_mut(|rows| {
+                rows.0.insert(1, 1);
+            });
+        });
+        assert_eq!(notified(&log), vec![0, 1]);
+    }
+
+    #[test]
+    fn changes_can_mark_every_key() {
+        let (dispatch, log, _ids) = setup();
+
+        dispatch.reduce_keyed(|rows: Rc<Rows>| {
+            let mut rows = (*rows).clone();
+            rows.0.clear();
+            (rows.into(), Changes::All)
+        });
+
+        assert_eq!(notified(&log), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn dropped_subscribers_are_not_notified() {
+        let (dispatch, log, mut ids) = setup();
+
+        ids.remove(1);
+        dispatch.reduce_mut_keyed(set(1, 1));
+        assert!(notified(&log).is_empty());
+
+        // The store itself is unsubscribed from once no keyed subscribers are left.
+        ids.clear();
+        assert!(dispatch
+            .context()
+            .keyed_index::<Rows>()
+            .borrow()
+            .subscription
+            .is_none());
+    }
+}
@@ -0,0 +1,316 @@
+//! Protect async reducers from losing changes made while they run.
+//!
+//! An async reducer reads the state when it starts, and writes its result back when it finishes.
+//! Any change made in the meantime would be lost. With
+//! [`Dispatch::reduce_future_checked`](crate::dispatch::Dispatch::reduce_future_checked), a change
+//! made in the meantime is detected, and handled with [`OnConflict`].
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use yewdux::{conflict::OnConflict, prelude::*};
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Cart {
+//!     items: Vec<u32>,
+//! }
+//!
+//! async fn fetch_recommended() -> u32 {
+//!     1
+//! }
+//!
+//! # async fn run() {
+//! let dispatch = Dispatch::<Cart>::new();
+//! dispatch
+//!     .reduce_future_checked(OnConflict::Retry(3), |cart: Rc<Cart>| async move {
+//!         let mut cart = (*cart).clone();
+//!         cart.items.push(fetch_recommended().await);
+//!         cart.into()
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use std::{any::type_name, future::Future, rc::Rc};
+
+use crate::{context::Context, store::Store};
+
+type Merge<S> = Box<dyn Fn(Rc<S>, Rc<S>, Rc<S>) -> Rc<S>>;
+
+/// What to do when state changed while an async reducer was running.
+pub enum OnConflict<S> {
+    /// Run the reducer again against the new state, up to this many more times.
+    Retry(usize),
+    /// Combine the changes. Called with the state the reducer started with, the state it was
+    /// changed to in the meantime, and the result of the reducer.
+    Merge(Merge<S>),
+    /// Leave the state as it is, returning [`CheckedError::Conflict`].
+    Fail,
+}
+
+impl<S> OnConflict<S> {
+    /// Combine the changes with `f`. See [`OnConflict::Merge`].
+    pub fn merge(f: impl Fn(Rc<S>, Rc<S>, Rc<S>) -> Rc<S> + 'static) -> Self {
+        Self::Merge(Box::new(f))
+    }
+}
+
+/// Returned when state changed while an async reducer was running, and the change could not be
+/// resolved.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{store} was changed while an async reducer was running")]
+pub struct Conflict {
+    /// Type name of the store.
+    pub store: &'static str,
+}
+
+/// An error changing state with
+/// [`Dispatch::reduce_future_checked`](crate::dispatch::Dispatch::reduce_future_checked).
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum CheckedError {
+    #[error(transparent)]
+    Conflict(#[from] Conflict),
+    #[error("The change to {store} was vetoed by middleware")]
+    Vetoed { store: &'static str },
+}
+
+impl Context {
+    /// Change state from a future, handling changes made while it runs with `on_conflict`.
+    pub(crate) async fn reduce_future_checked<S, FUT, FUN>(
+        &self,
+        on_conflict: OnConflict<S>,
+        f: FUN,
+    ) -> Result<(), CheckedError>
+    where
+        S: Store,
+        FUT: Future<Output = Rc<S>>,
+        FUN: Fn(Rc<S>) -> FUT,
+    {
+        self.assert_writable::<S>();
+
+        let conflict = Conflict {
+            store: type_name::<S>(),
+        };
+        let mut retries = 0;
+        let new = loop {
+            let base = self.get::<S>();
+            let new = f(Rc::clone(&base)).await;
+            let current = self.get::<S>();
+            // Changes that didn't notify, such as no-op reduces, aren't conflicts.
+            if Rc::ptr_eq(&base, &current) || !current.should_notify(&base) {
+                break new;
+            }
+
+            match &on_conflict {
+                OnConflict::Retry(max) if retries < *max => retries += 1,
+                OnConflict::Retry(_) | OnConflict::Fail => return Err(conflict.into()),
+                OnConflict::Merge(merge) => break merge(base, current, new),
+            }
+        };
+
+        // Nothing can change between checking and committing, as this doesn't await.
+        if !self.reduce_named(type_name::<FUN>(), move |_| new) {
+            return Err(CheckedError::Vetoed {
+                store: type_name::<S>(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Run a future with the current state, then change the state as it is once the future is
+    /// done.
+    pub(crate) async fn reduce_mut_future_then<S, T, FUT, FUN, M>(&self, f: FUN, then: M)
+    where
+        S: Store + Clone,
+        FUT: Future<Output = T>,
+        FUN: FnOnce(Rc<S>) -> FUT,
+        M: FnOnce(&mut S, T),
+    {
+        let value = f(self.get::<S>()).await;
+        self.reduce_mut(move |state| then(state, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::{dispatch::Dispatch, test_util::isolated};
+
+    use super::*;
+
+    /// Numbers pushed so far.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    struct Pushed(Vec<u32>);
+    impl Store for Pushed {
+        fn new() -> Self {
+            Self(Vec::new())
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    fn setup() -> (Dispatch<Pushed>, Rc<Cell<u32>>) {
+        (isolated(), Rc::new(Cell::new(0)))
+    }
+
+    /// Pushes `n`. The first time, the state is changed concurrently while it runs.
+    fn push(
+        dispatch: &Dispatch<Pushed>,
+        runs: &Rc<Cell<u32>>,
+        n: u32,
+    ) -> impl Fn(Rc<Pushed>) -> std::pin::Pin<Box<dyn Future<Output = Rc<Pushed>>>> {
+        let dispatch = dispatch.clone();
+        let runs = runs.clone();
+        move |state| {
+            let dispatch = dispatch.clone();
+            let runs = runs.clone();
+            Box::pin(async move {
+                runs.set(runs.get() + 1);
+                if runs.get() == 1 {
+                    dispatch.reduce_mut(|state| state.0.push(0));
+                }
+
+                let mut state = (*state).clone();
+                state.0.push(n);
+                state.into()
+            })
+        }
+    }
+
+    #[async_std::test]
+    async fn no_conflict_is_applied() {
+        let (dispatch, runs) = setup();
+        runs.set(1);
+
+        let result = dispatch
+            .reduce_future_checked(OnConflict::Fail, push(&dispatch, &runs, 1))
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(dispatch.get().0, vec![1]);
+    }
+
+    #[async_std::test]
+    async fn conflict_fails() {
+        let (dispatch, runs) = setup();
+
+        let result = dispatch
+            .reduce_future_checked(OnConflict::Fail, push(&dispatch, &runs, 1))
+            .await;
+
+        assert_eq!(
+            result,
+            Err(CheckedError::Conflict(Conflict {
+                store: type_name::<Pushed>()
+            }))
+        );
+        // The concurrent change is kept.
+        assert_eq!(dispatch.get().0, vec![0]);
+    }
+
+    #[async_std::test]
+    async fn unchanged_state_is_not_a_conflict() {
+        let (dispatch, _) = setup();
+
+        let result = dispatch
+            .reduce_future_checked(OnConflict::Fail, |state: Rc<Pushed>| {
+                let dispatch = dispatch.clone();
+                async move {
+                    dispatch.reduce_mut(|_| {});
+                    let mut state = (*state).clone();
+                    state.0.push(1);
+                    state.into()
+                }
+            })
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(dispatch.get().0, vec![1]);
+    }
+
+    #[async_std::test]
+    async fn vetoed_change_fails() {
+        let (dispatch, runs) = setup();
+        runs.set(1);
+        crate::middleware::register_with_context::<Pushed, _>(dispatch.context(), |_| None);
+
+        let result = dispatch
+            .reduce_future_checked(OnConflict::Fail, push(&dispatch, &runs, 1))
+            .await;
+
+        assert_eq!(
+            result,
+            Err(CheckedError::Vetoed {
+                store: type_name::<Pushed>()
+            })
+        );
+        assert!(dispatch.get().0.is_empty());
+    }
+
+    #[async_std::test]
+    async fn conflict_retries() {
+        let (dispatch, runs) = setup();
+
+        let result = dispatch
+            .reduce_future_checked(OnConflict::Retry(1), push(&dispatch, &runs, 1))
+            .await;
+
+        assert!(result.is_ok());
+        assert!(runs.get() == 2);
+        assert_eq!(dispatch.get().0, vec![0, 1]);
+    }
+
+    #[async_std::test]
+    async fn retries_run_out() {
+        let (dispatch, runs) = setup();
+
+        let result = dispatch
+            .reduce_future_checked(OnConflict::Retry(0), push(&dispatch, &runs, 1))
+            .await;
+
+        assert!(result.is_err());
+        assert!(runs.get() == 1);
+    }
+
+    #[async_std::test]
+    async fn conflict_merges() {
+        let (dispatch, runs) = setup();
+        let merge = OnConflict::merge(|base, current: Rc<Pushed>, new: Rc<Pushed>| {
+            assert!(base.0.is_empty());
+            let mut merged = (*current).clone();
+            merged.0.extend(&new.0);
+            merged.into()
+        });
+
+        let result = dispatch
+            .reduce_future_checked(merge, push(&dispatch, &runs, 1))
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(dispatch.get().0, vec![0, 1]);
+    }
+
+    #[async_std::test]
+    async fn mut_future_then_keeps_concurrent_changes() {
+        let (dispatch, _) = setup();
+
+        dispatch
+            .reduce_mut_future_then(
+                |state| {
+                    let dispatch = dispatch.clone();
+                    async move {
+                        dispatch.reduce_mut(|state| state.0.push(0));
+                        state.0.len() as u32 + 1
+                    }
+                },
+                |state, n| state.0.push(n),
+            )
+            .await;
+
+        assert_eq!(dispatch.get().0, vec![0, 1]);
+    }
+}
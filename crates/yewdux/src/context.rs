@@ -1,17 +1,35 @@
-use std::rc::Rc;
+//! Holds all shared state.
+//!
+//! By default every store lives in a single, thread local [`Context`]. A new context can be
+//! created to get a completely separate set of stores, which is how
+//! [`YewduxRoot`](crate::context_provider::YewduxRoot) scopes stores to a part of the component
+//! tree.
+use std::{
+    any::{type_name, TypeId},
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    marker::PhantomData,
+    rc::{Rc, Weak},
+};
+#[cfg(feature = "future")]
+use std::{future::Future, pin::Pin};
 
 use anymap::AnyMap;
 
+#[cfg(feature = "future")]
+use crate::task::Task;
 use crate::{
+    middleware,
     mrc::Mrc,
     store::{AsyncReducer, Reducer, Store},
+    subscriber::{Callable, SubscriberId, Subscribers},
 };
 
-pub(crate) struct Context<S> {
+pub(crate) struct Entry<S> {
     pub(crate) store: Mrc<Rc<S>>,
 }
 
-impl<S> Clone for Context<S> {
+impl<S> Clone for Entry<S> {
     fn clone(&self) -> Self {
         Self {
             store: Mrc::clone(&self.store),
@@ -19,80 +37,787 @@ impl<S> Clone for Context<S> {
     }
 }
 
-impl<S: Store> Context<S> {
-    /// Apply a function to state, returning if it should notify subscribers or not.
-    pub(crate) fn reduce<R: Reducer<S>>(&self, reducer: R) -> bool {
+impl<S: Store> Entry<S> {
+    /// Apply a function to state, returning if it should notify subscribers or not. Returns `None`
+    /// if middleware vetoed the change.
+    pub(crate) fn reduce<R: Reducer<S>>(&self, cx: &Context, reducer: R) -> Option<bool> {
         let old = Rc::clone(&self.store.borrow());
         // Apply the reducer.
         let new = reducer.apply(Rc::clone(&old));
+        // Let middleware have a say, it may veto the change.
+        let new = middleware::run(cx, Rc::clone(&old), new)?;
         // Update to new state.
         *self.store.borrow_mut() = new;
         // Return whether or not subscribers should be notified.
-        self.store.borrow().should_notify(&old)
+        let should_notify = self.store.borrow().should_notify(&old);
+        Some(should_notify)
     }
 
-    /// Apply a future reduction to state, returning if it should notify subscribers or not.
+    /// Apply a future reduction to state, returning if it should notify subscribers or not. Returns
+    /// `None` if middleware vetoed the change, or `task` was cancelled before it finished.
     #[cfg(feature = "future")]
-    pub(crate) async fn reduce_future<R: AsyncReducer<S>>(&self, reducer: R) -> bool {
+    pub(crate) async fn reduce_future<R: AsyncReducer<S>>(
+        &self,
+        cx: &Context,
+        reducer: R,
+        task: Option<&Task>,
+    ) -> Option<bool> {
         let old = Rc::clone(&self.store.borrow());
-        // Apply the reducer.
-        let new = reducer.apply(Rc::clone(&old)).await;
+        // Apply the reducer, unless cancelled first.
+        let new = match task {
+            Some(task) => task.until_cancelled(reducer.apply(Rc::clone(&old))).await?,
+            None => reducer.apply(Rc::clone(&old)).await,
+        };
+        // Let middleware have a say, it may veto the change.
+        let new = middleware::run(cx, Rc::clone(&old), new)?;
         // Update the new state.
         *self.store.borrow_mut() = new;
         // Return whether or not subscribers should be notified.
-        self.store.borrow().should_notify(&old)
+        let should_notify = self.store.borrow().should_notify(&old);
+        Some(should_notify)
     }
 }
 
-pub(crate) fn get_or_init<S: Store>() -> Context<S> {
-    thread_local! {
-        /// Holds all shared state.
-        static CONTEXTS: Mrc<AnyMap> = Mrc::new(AnyMap::new());
-    }
-
-    let contexts = CONTEXTS
-        .try_with(|contexts| contexts.clone())
-        .expect("CONTEXTS thread local key init failed");
-
-    // Get context, or None if it doesn't exist.
-    //
-    // We use an option here because a new Store should not be created during this borrow. We want
-    // to allow this store access to other stores during creation, so cannot be borrowing the
-    // global resource while initializing. Instead we create a temporary placeholder, which
-    // indicates the store needs to be created. Without this indicator we would have needed to
-    // check if the map contains the entry beforehand, which would have meant two map lookups per
-    // call instead of just one.
-    let maybe_context = contexts.with_mut(|x| {
-        x.entry::<Mrc<Option<Context<S>>>>()
+type Notify = Box<dyn FnOnce()>;
+
+/// Notifications held back until the outermost batch is done.
+#[derive(Default)]
+struct Batch {
+    depth: Cell<usize>,
+    /// One notification per store, in order of first change.
+    pending: RefCell<Vec<(TypeId, Context, Notify)>>,
+    /// Effects to run once every notification is sent.
+    effects: RefCell<Vec<Notify>>,
+}
+
+impl Batch {
+    fn defer<S: Store>(&self, cx: &Context, old: Rc<S>) {
+        let mut pending = self.pending.borrow_mut();
+        // Keep the state from before the batch, so we can tell if anything changed overall.
+        let exists = pending
+            .iter()
+            .any(|(id, other, _)| *id == TypeId::of::<S>() && other == cx);
+        if !exists {
+            let notify = {
+                let cx = cx.clone();
+                Box::new(move || {
+                    let state = cx.get::<S>();
+                    if state.should_notify(&old) {
+                        cx.notify_subscribers(state);
+                    }
+                })
+            };
+            pending.push((TypeId::of::<S>(), cx.clone(), notify));
+        }
+    }
+}
+
+/// Number of recent changes included when reporting an update loop.
+const CHAIN_LEN: usize = 10;
+
+/// Tracks changes made while notifying subscribers, to catch update loops.
+struct Updates {
+    max_notify_depth: Cell<usize>,
+    depth: Cell<usize>,
+    /// Most recent changes, as `(store, reducer)` type names.
+    chain: RefCell<VecDeque<(&'static str, &'static str)>>,
+}
+
+impl Default for Updates {
+    fn default() -> Self {
+        Self {
+            max_notify_depth: Cell::new(100),
+            depth: Default::default(),
+            chain: Default::default(),
+        }
+    }
+}
+
+impl Updates {
+    fn record<S: Store>(&self, reducer: &'static str) {
+        let mut chain = self.chain.borrow_mut();
+        if self.depth.get() == 0 {
+            // A new update, unrelated to previous ones.
+            chain.clear();
+        }
+        if chain.len() == CHAIN_LEN {
+            chain.pop_front();
+        }
+        chain.push_back((type_name::<S>(), reducer));
+    }
+
+    /// Panic if `S` is about to be notified `depth` levels deep, see
+    /// [`Context::set_max_notify_depth`].
+    fn check_depth<S: Store>(&self, depth: usize) {
+        let max = self.max_notify_depth.get();
+        if depth > max {
+            let chain = self
+                .chain
+                .borrow()
+                .iter()
+                .map(|(store, reducer)| format!("\n  {store} <- {reducer}"))
+                .collect::<String>();
+            panic!(
+                "Possible infinite update loop: {} was notified recursively more than {} times. \
+                 Most recent changes (store <- reducer):{}",
+                type_name::<S>(),
+                max,
+                chain
+            );
+        }
+    }
+}
+
+/// A collection of stores, along with their subscribers.
+///
+/// Cloning a context is cheap, and gives another handle to the same stores.
+#[derive(Clone, Default)]
+pub struct Context {
+    inner: Rc<RefCell<AnyMap>>,
+    batch: Rc<Batch>,
+    updates: Rc<Updates>,
+}
+
+impl Context {
+    /// Create a new context with no stores. Stores are created on first access, same as the
+    /// global context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The context used when no other context is provided.
+    pub fn global() -> Self {
+        thread_local! {
+            /// Holds all shared state.
+            static CONTEXT: Context = Default::default();
+        }
+
+        CONTEXT
+            .try_with(|cx| cx.clone())
+            .expect("CONTEXT thread local key init failed")
+    }
+
+    /// Create an empty context that shares batches with this one.
+    pub(crate) fn child(&self) -> Self {
+        Self {
+            inner: Default::default(),
+            batch: Rc::clone(&self.batch),
+            updates: Rc::clone(&self.updates),
+        }
+    }
+
+    /// Initialize a store with the given value, instead of [`Store::new`]. Does nothing if the
+    /// store already exists.
+    pub(crate) fn init<S: Store>(&self, value: S) {
+        let maybe_entry = self
+            .inner
+            .borrow_mut()
+            .entry::<Mrc<Option<Entry<S>>>>()
             .or_insert_with(|| None.into())
+            .clone();
+
+        let mut maybe_entry = maybe_entry.borrow_mut();
+        if maybe_entry.is_none() {
+            *maybe_entry = Some(Entry {
+                store: Mrc::new(Rc::new(value)),
+            });
+        }
+    }
+
+    /// Get the entry for `S`, creating the store if needed.
+    pub(crate) fn get_or_init<S: Store>(&self) -> Entry<S> {
+        self.get_or_init_with(|cx| {
+            cx.register::<S>(reset_store::<S>);
+            cx.restore_pending::<S>();
+        })
+    }
+
+    /// Same as [`Self::get_or_init`], for stores used internally. These are not registered, so
+    /// aren't reset with [`Self::reset_all`].
+    pub(crate) fn get_or_init_internal<S: Store>(&self) -> Entry<S> {
+        self.get_or_init_with(|_| {})
+    }
+
+    fn get_or_init_with<S: Store>(&self, on_init: impl FnOnce(&Self)) -> Entry<S> {
+        // Get context, or None if it doesn't exist.
+        //
+        // We use an option here because a new Store should not be created during this borrow. We
+        // want to allow this store access to other stores during creation, so cannot be borrowing
+        // the global resource while initializing. Instead we create a temporary placeholder, which
+        // indicates the store needs to be created. Without this indicator we would have needed to
+        // check if the map contains the entry beforehand, which would have meant two map lookups
+        // per call instead of just one.
+        let maybe_entry = self
+            .inner
+            .borrow_mut()
+            .entry::<Mrc<Option<Entry<S>>>>()
+            .or_insert_with(|| None.into())
+            .clone();
+
+        // If it doesn't exist, create and store the entry.
+        let exists = maybe_entry.borrow().is_some();
+        if !exists {
+            // Init store outside of borrow. This allows the store to access other stores when it
+            // is being created.
+            let entry = Entry {
+                store: Mrc::new(Rc::new(S::new_with_context(self))),
+            };
+
+            *maybe_entry.borrow_mut() = Some(entry);
+            on_init(self);
+        }
+
+        // Now we get the entry, which must be initialized because we already checked above.
+        let entry = maybe_entry
+            .borrow()
             .clone()
-    });
-
-    // If it doesn't exist, create and store the context (no pun intended).
-    let exists = maybe_context.borrow().is_some();
-    if !exists {
-        // Init context outside of borrow. This allows the store to access other stores when it is
-        // being created.
-        let context = Context {
-            store: Mrc::new(Rc::new(S::new())),
+            .expect("Context not initialized");
+
+        entry
+    }
+
+    /// Get the current state, if the store has been initialized.
+    pub(crate) fn try_get<S: Store>(&self) -> Option<Rc<S>> {
+        let maybe_entry = self
+            .inner
+            .borrow()
+            .get::<Mrc<Option<Entry<S>>>>()
+            .cloned()?;
+        let entry = maybe_entry.borrow().clone()?;
+        let state = Rc::clone(&entry.store.borrow());
+
+        Some(state)
+    }
+
+    /// Run `f`, holding back notifications until it is done. Each changed store then notifies
+    /// its subscribers once, with its final state. Batches may be nested, in which case
+    /// notifications are sent when the outermost batch is done.
+    pub(crate) fn batch<R>(&self, f: impl FnOnce() -> R) -> R {
+        let result = self.batched(f);
+
+        if self.batch.depth.get() == 0 {
+            // Notifications are sent in a batch too, so changes made by subscribers (e.g. derived
+            // stores) are collapsed as well. Keep going until nothing changes.
+            self.update(|| loop {
+                let pending = std::mem::take(&mut *self.batch.pending.borrow_mut());
+                if pending.is_empty() {
+                    break;
+                }
+
+                self.batched(|| {
+                    for (.., notify) in pending {
+                        notify();
+                    }
+                });
+            });
+
+            let effects = std::mem::take(&mut *self.batch.effects.borrow_mut());
+            for effect in effects {
+                effect();
+            }
+        }
+
+        result
+    }
+
+    /// Run `f` as part of an update. Changes made until the outermost update is done are kept, to
+    /// report update loops.
+    fn update<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Guard<'a>(&'a Updates);
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                self.0.depth.set(self.0.depth.get() - 1);
+            }
+        }
+
+        self.updates.depth.set(self.updates.depth.get() + 1);
+        let _guard = Guard(&self.updates);
+
+        f()
+    }
+
+    /// Call `f` after every change, with the store and reducer type names.
+    #[cfg(feature = "devtools")]
+    pub(crate) fn observe(&self, f: impl Fn(&Context, &'static str, &'static str) + 'static) {
+        self.inner
+            .borrow_mut()
+            .entry::<Observers>()
+            .or_default()
+            .0
+            .push(Rc::new(f));
+    }
+
+    #[cfg(feature = "devtools")]
+    fn observed(&self, store: &'static str, reducer: &'static str) {
+        let observers = match self.inner.borrow().get::<Observers>() {
+            Some(observers) => observers.0.clone(),
+            None => return,
         };
 
-        *maybe_context.borrow_mut() = Some(context);
+        for observer in observers {
+            observer(self, store, reducer);
+        }
+    }
+
+    /// Set how deeply a store may be notified recursively, that is changed again by subscribers
+    /// while it's notifying them. Going over this is considered an infinite loop, and panics with
+    /// the changes that led to it. Defaults to 100.
+    ///
+    /// Changes that aren't nested, such as many subscribers of another store each changing this
+    /// one once, don't count towards the limit.
+    pub fn set_max_notify_depth(&self, depth: usize) {
+        self.updates.max_notify_depth.set(depth);
+    }
+
+    fn batched<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Guard<'a>(&'a Batch);
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                self.0.depth.set(self.0.depth.get() - 1);
+                // Don't leave notifications behind for an unrelated batch.
+                if self.0.depth.get() == 0 && std::thread::panicking() {
+                    self.0.pending.borrow_mut().clear();
+                    self.0.effects.borrow_mut().clear();
+                }
+            }
+        }
+
+        self.batch.depth.set(self.batch.depth.get() + 1);
+        let _guard = Guard(&self.batch);
+
+        f()
     }
 
-    // Now we get the context, which must be initialized because we already checked above.
-    let context = maybe_context
-        .borrow()
-        .clone()
-        .expect("Context not initialized");
+    /// Number of notifications and effects currently held back by a batch.
+    pub(crate) fn pending_len(&self) -> (usize, usize) {
+        (
+            self.batch.pending.borrow().len(),
+            self.batch.effects.borrow().len(),
+        )
+    }
+
+    /// Drop notifications and effects held back since [`Self::pending_len`] returned `len`.
+    pub(crate) fn discard_pending(&self, len: (usize, usize)) {
+        self.batch.pending.borrow_mut().truncate(len.0);
+        self.batch.effects.borrow_mut().truncate(len.1);
+    }
+
+    /// Run `f` once subscribers are notified, which is after the outermost batch if in one.
+    pub(crate) fn after_notify(&self, f: Notify) {
+        if self.batch.depth.get() > 0 {
+            self.batch.effects.borrow_mut().push(f);
+        } else {
+            f();
+        }
+    }
+
+    /// Notify subscribers of a change, or defer it if in a batch.
+    fn changed<S: Store>(&self, reducer: &'static str, old: Rc<S>, entry: &Entry<S>) {
+        #[cfg(feature = "devtools")]
+        self.observed(type_name::<S>(), reducer);
+        #[cfg(not(feature = "devtools"))]
+        let _ = reducer;
+
+        if self.batch.depth.get() > 0 {
+            self.batch.defer(self, old);
+        } else {
+            let state = Rc::clone(&entry.store.borrow());
+            self.notify_subscribers(state)
+        }
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakContext {
+        WeakContext {
+            inner: Rc::downgrade(&self.inner),
+            batch: Rc::downgrade(&self.batch),
+            updates: Rc::downgrade(&self.updates),
+        }
+    }
+
+    /// Prevent reducers from changing `S`. It may still be changed with
+    /// [`Self::reduce_unchecked`].
+    pub(crate) fn set_read_only<S: Store>(&self) {
+        self.inner.borrow_mut().insert(ReadOnly::<S>(PhantomData));
+    }
+
+    pub(crate) fn assert_writable<S: Store>(&self) {
+        // Make sure the store exists, as creating it may mark it read only.
+        self.get_or_init::<S>();
+        if self.inner.borrow().contains::<ReadOnly<S>>() {
+            panic!(
+                "{} is read only, and cannot be changed by reducers",
+                type_name::<S>()
+            );
+        }
+    }
+
+    /// Change state from a function.
+    pub(crate) fn reduce<S: Store, R: Reducer<S>>(&self, r: R) {
+        self.assert_writable::<S>();
+        self.reduce_named(type_name::<R>(), r);
+    }
+
+    /// Change state from a function, even if it is read only.
+    pub(crate) fn reduce_unchecked<S: Store, R: Reducer<S>>(&self, r: R) {
+        self.reduce_named(type_name::<R>(), r);
+    }
+
+    /// Change state from a function, even if it is read only. `reducer` names the change when
+    /// reporting update loops. Returns false if middleware vetoed the change.
+    pub(crate) fn reduce_named<S: Store, R: Reducer<S>>(
+        &self,
+        reducer: &'static str,
+        r: R,
+    ) -> bool {
+        self.updates.record::<S>(reducer);
+        let entry = self.get_or_init::<S>();
+        let old = Rc::clone(&entry.store.borrow());
+        let should_notify = match entry.reduce(self, r) {
+            Some(should_notify) => should_notify,
+            None => return false,
+        };
+
+        if should_notify {
+            self.changed(reducer, old, &entry);
+        }
+
+        true
+    }
 
-    context
+    #[cfg(feature = "future")]
+    pub(crate) async fn reduce_future<S, R>(&self, r: R)
+    where
+        S: Store,
+        R: AsyncReducer<S>,
+    {
+        self.reduce_future_task(r, None).await;
+    }
+
+    /// Change state from a future, which is abandoned if `task` is cancelled. Returns false if the
+    /// change was cancelled or vetoed by middleware.
+    #[cfg(feature = "future")]
+    pub(crate) async fn reduce_future_task<S, R>(&self, r: R, task: Option<&Task>) -> bool
+    where
+        S: Store,
+        R: AsyncReducer<S>,
+    {
+        self.assert_writable::<S>();
+        self.updates.record::<S>(type_name::<R>());
+        let entry = self.get_or_init::<S>();
+        let old = Rc::clone(&entry.store.borrow());
+        let should_notify = match entry.reduce_future(self, r, task).await {
+            Some(should_notify) => should_notify,
+            None => return false,
+        };
+
+        if should_notify {
+            self.changed(type_name::<R>(), old, &entry);
+        }
+
+        true
+    }
+
+    /// Change state using a mutable reference from a function.
+    pub(crate) fn reduce_mut<S: Store + Clone, F: FnOnce(&mut S)>(&self, f: F) {
+        self.assert_writable::<S>();
+        self.reduce_named(type_name::<F>(), |mut state: Rc<S>| {
+            f(Rc::make_mut(&mut state));
+            state
+        });
+    }
+
+    #[cfg(feature = "future")]
+    pub(crate) async fn reduce_mut_future<S, R, F>(&self, f: F)
+    where
+        S: Store + Clone,
+        F: FnOnce(&mut S) -> Pin<Box<dyn Future<Output = R> + '_>>,
+    {
+        self.reduce_future(|mut state| async move {
+            f(Rc::make_mut(&mut state)).await;
+            state
+        })
+        .await;
+    }
+
+    /// Set state to given value.
+    pub(crate) fn set<S: Store>(&self, value: S) {
+        self.assert_writable::<S>();
+        self.reduce_named("set", move |_| value.into());
+    }
+
+    /// Get current state.
+    pub(crate) fn get<S: Store>(&self) -> Rc<S> {
+        Rc::clone(&self.get_or_init::<S>().store.borrow())
+    }
+
+    /// Send state to all subscribers.
+    pub(crate) fn notify_subscribers<S: Store>(&self, state: Rc<S>) {
+        let entry = self.get_or_init_internal::<Mrc<Subscribers<S>>>();
+        self.update(|| {
+            let subscribers = Rc::clone(&entry.store.borrow());
+            self.updates
+                .check_depth::<S>(subscribers.borrow().next_depth());
+            subscribers.notify(state);
+        });
+    }
+
+    /// Subscribe to a store. `on_change` is called immediately, then every  time state changes.
+    pub(crate) fn subscribe<S: Store, N: Callable<S>>(&self, on_change: N) -> SubscriberId<S> {
+        // Notify subscriber with inital state.
+        on_change.call(self.get::<S>());
+
+        self.subscribe_silent(on_change)
+    }
+
+    /// Similar to [Self::subscribe], however state is not called immediately.
+    pub(crate) fn subscribe_silent<S: Store, N: Callable<S>>(
+        &self,
+        on_change: N,
+    ) -> SubscriberId<S> {
+        self.get_or_init_internal::<Mrc<Subscribers<S>>>()
+            .store
+            .borrow()
+            .subscribe(self, on_change)
+    }
+
+    /// Register a store, so it is reset with [`Self::reset_all`] and listed in
+    /// [`Self::stores`](crate::registry). Does nothing if it is already registered.
+    pub(crate) fn register<S: Store>(&self, reset: Reset) {
+        self.register_with::<S>(reset, false);
+    }
+
+    /// Same as [`Self::register`], for stores used internally. These are reset, but not listed.
+    pub(crate) fn register_internal<S: Store>(&self, reset: Reset) {
+        self.register_with::<S>(reset, true);
+    }
+
+    fn register_with<S: Store>(&self, reset: Reset, internal: bool) {
+        let mut inner = self.inner.borrow_mut();
+        let registry = &mut inner.entry::<Registry>().or_default().0;
+        if !registry
+            .iter()
+            .any(|store| store.type_id == TypeId::of::<S>())
+        {
+            registry.push(Registered {
+                type_id: TypeId::of::<S>(),
+                type_name: type_name::<S>(),
+                reset,
+                internal,
+                initialized: |cx| cx.try_get::<S>().is_some(),
+                subscribers: subscriber_count::<S>,
+            });
+        }
+    }
+
+    /// Every registered store, in order of creation.
+    pub(crate) fn registered(&self) -> Vec<Registered> {
+        self.inner
+            .borrow()
+            .get::<Registry>()
+            .map(|registry| registry.0.clone())
+            .unwrap_or_default()
+    }
+
+    /// Reset `S` to its initial value, notifying subscribers if it changed.
+    pub(crate) fn reset<S: Store>(&self) {
+        if self.try_get::<S>().is_none() {
+            // Nothing to reset, just create it.
+            self.get_or_init::<S>();
+            return;
+        }
+
+        let value = <S as Store>::reset(self);
+        self.reset_hooks::<S>();
+        self.reduce_unchecked(move |_| Rc::new(value));
+    }
+
+    /// Call `f` whenever `S` is reset or dropped, to reset state kept alongside it.
+    pub(crate) fn on_reset<S: Store>(&self, f: fn(&Context)) {
+        self.inner
+            .borrow_mut()
+            .entry::<OnReset<S>>()
+            .or_insert_with(|| OnReset(Vec::new(), PhantomData))
+            .0
+            .push(f);
+    }
+
+    fn reset_hooks<S: Store>(&self) {
+        let hooks = self
+            .inner
+            .borrow()
+            .get::<OnReset<S>>()
+            .map(|hooks| hooks.0.clone())
+            .unwrap_or_default();
+        for hook in hooks {
+            hook(self);
+        }
+    }
+
+    /// Reset every store in this context to its initial value. Subscriptions are kept, and
+    /// subscribers are notified once every store is reset.
+    pub fn reset_all(&self) {
+        let registered = self.registered();
+
+        self.batch(|| {
+            for store in registered {
+                (store.reset)(self);
+            }
+        });
+    }
+
+    /// Number of [`Handle`]s to `S`.
+    fn handles<S: Store>(&self) -> usize {
+        self.inner
+            .borrow()
+            .get::<Handles<S>>()
+            .map(|handles| handles.count)
+            .unwrap_or_default()
+    }
+
+    /// Whether `S` has any [`Handle`]s or subscribers.
+    pub(crate) fn in_use<S: Store>(&self) -> bool {
+        self.handles::<S>() > 0 || subscriber_count::<S>(self) > 0
+    }
+
+    /// Drop `S` if nothing is using it, and it doesn't want to be retained. It is created again on
+    /// next access.
+    pub(crate) fn collect<S: Store>(&self) {
+        let state = match self.try_get::<S>() {
+            Some(state) => state,
+            None => return,
+        };
+        if state.retain() || self.in_use::<S>() {
+            return;
+        }
+
+        let mut inner = self.inner.borrow_mut();
+        inner.remove::<Mrc<Option<Entry<S>>>>();
+        // Nothing refers to these anymore either, so they would only take up space.
+        inner.remove::<Mrc<Option<Entry<Mrc<Subscribers<S>>>>>>();
+        inner.remove::<Handles<S>>();
+        drop(inner);
+
+        self.reset_hooks::<S>();
+        state.on_drop();
+    }
+}
+
+#[cfg(feature = "devtools")]
+type Observer = Rc<dyn Fn(&Context, &'static str, &'static str)>;
+
+/// Called after every change, see [`Context::observe`].
+#[cfg(feature = "devtools")]
+#[derive(Default)]
+struct Observers(Vec<Observer>);
+
+/// Every store created in a context, in order of creation, along with how to reset it.
+#[derive(Default)]
+struct Registry(Vec<Registered>);
+
+type Reset = fn(&Context);
+
+#[derive(Clone)]
+pub(crate) struct Registered {
+    pub(crate) type_id: TypeId,
+    pub(crate) type_name: &'static str,
+    reset: Reset,
+    /// Not listed in [`Context::stores`].
+    pub(crate) internal: bool,
+    pub(crate) initialized: fn(&Context) -> bool,
+    pub(crate) subscribers: fn(&Context) -> usize,
+}
+
+fn subscriber_count<S: Store>(cx: &Context) -> usize {
+    cx.try_get::<Mrc<Subscribers<S>>>()
+        .map(|subscribers| subscribers.borrow().len())
+        .unwrap_or_default()
+}
+
+fn reset_store<S: Store>(cx: &Context) {
+    // It may have been dropped since, see `Store::retain`.
+    if cx.try_get::<S>().is_some() {
+        cx.reset::<S>();
+    }
+}
+
+/// Keeps a store in use, so it isn't dropped while held. See [`Store::retain`].
+pub(crate) struct Handle<S: Store> {
+    cx: WeakContext,
+    _store_type: PhantomData<S>,
+}
+
+impl<S: Store> Handle<S> {
+    pub(crate) fn new(cx: &Context) -> Self {
+        cx.inner
+            .borrow_mut()
+            .entry::<Handles<S>>()
+            .or_insert_with(|| Handles {
+                count: 0,
+                _store_type: PhantomData,
+            })
+            .count += 1;
+
+        Self {
+            cx: cx.downgrade(),
+            _store_type: PhantomData,
+        }
+    }
+}
+
+impl<S: Store> Drop for Handle<S> {
+    fn drop(&mut self) {
+        if let Some(cx) = self.cx.upgrade() {
+            if let Some(handles) = cx.inner.borrow_mut().get_mut::<Handles<S>>() {
+                handles.count -= 1;
+            }
+            cx.collect::<S>();
+        }
+    }
+}
+
+/// Number of live [`Handle`]s to `S`.
+struct Handles<S> {
+    count: usize,
+    _store_type: PhantomData<S>,
+}
+
+/// A [`Context`] that doesn't keep its stores alive. Used by subscriptions held by the context
+/// itself, which would otherwise never be dropped.
+#[derive(Clone)]
+pub(crate) struct WeakContext {
+    inner: Weak<RefCell<AnyMap>>,
+    batch: Weak<Batch>,
+    updates: Weak<Updates>,
+}
+
+impl WeakContext {
+    pub(crate) fn upgrade(&self) -> Option<Context> {
+        Some(Context {
+            inner: self.inner.upgrade()?,
+            batch: self.batch.upgrade()?,
+            updates: self.updates.upgrade()?,
+        })
+    }
+}
+
+/// Marks `S` as read only.
+struct ReadOnly<S>(PhantomData<S>);
+
+/// Called when `S` is reset or dropped, see [`Context::on_reset`].
+struct OnReset<S>(Vec<fn(&Context)>, PhantomData<S>);
+
+impl PartialEq for Context {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::cell::Cell;
 
+    use crate::dispatch::Dispatch;
+
     use super::*;
 
     #[derive(Clone, PartialEq, Eq)]
@@ -111,7 +836,7 @@ mod tests {
     struct TestState2(u32);
     impl Store for TestState2 {
         fn new() -> Self {
-            get_or_init::<TestState>();
+            Context::global().get_or_init::<TestState>();
             Self(0)
         }
 
@@ -122,7 +847,7 @@ mod tests {
 
     #[test]
     fn can_access_other_store_for_new_of_current_store() {
-        let _context = get_or_init::<TestState2>();
+        let _context = Context::global().get_or_init::<TestState2>();
     }
 
     #[derive(Clone, PartialEq, Eq)]
@@ -148,9 +873,207 @@ mod tests {
 
     #[test]
     fn store_new_is_only_called_once() {
-        get_or_init::<StoreNewIsOnlyCalledOnce>();
-        let context = get_or_init::<StoreNewIsOnlyCalledOnce>();
+        Context::global().get_or_init::<StoreNewIsOnlyCalledOnce>();
+        let context = Context::global().get_or_init::<StoreNewIsOnlyCalledOnce>();
 
         assert!(context.store.borrow().0.get() == 1)
     }
+
+    #[test]
+    fn contexts_do_not_share_stores() {
+        let cx1 = Context::new();
+        let cx2 = Context::new();
+
+        cx1.set(TestState(1));
+
+        assert!(cx1.get::<TestState>().0 == 1);
+        assert!(cx2.get::<TestState>().0 == 0);
+        assert!(Context::global().get::<TestState>().0 == 0);
+    }
+
+    #[test]
+    fn subscribers_are_scoped_to_context() {
+        let cx1 = Context::new();
+        let cx2 = Context::new();
+        let flag = Mrc::new(false);
+
+        let _id = {
+            let flag = flag.clone();
+            cx1.subscribe_silent(move |_: Rc<TestState>| *flag.borrow_mut() = true)
+        };
+
+        cx2.set(TestState(1));
+        assert!(!*flag.borrow());
+
+        cx1.set(TestState(1));
+        assert!(*flag.borrow());
+    }
+
+    #[test]
+    fn cloned_context_is_equal() {
+        let cx = Context::new();
+
+        assert!(cx == cx.clone());
+        assert!(cx != Context::new());
+        assert!(Context::global() == Context::global());
+    }
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Unused(u32);
+    impl Store for Unused {
+        fn new() -> Self {
+            Self(0)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+
+        fn retain(&self) -> bool {
+            false
+        }
+
+        fn on_drop(&self) {
+            DROPPED.with(|dropped| dropped.set(self.0));
+        }
+    }
+
+    thread_local! {
+        static DROPPED: Cell<u32> = Default::default();
+    }
+
+    #[test]
+    fn store_is_dropped_when_unused() {
+        let cx = Context::new();
+        let dispatch = Dispatch::<Unused>::with_context(&cx);
+        dispatch.set(Unused(1));
+
+        drop(dispatch);
+
+        assert!(cx.try_get::<Unused>().is_none());
+        assert!(DROPPED.with(|dropped| dropped.get()) == 1);
+        assert!(cx.get::<Unused>().0 == 0);
+    }
+
+    #[test]
+    fn store_is_kept_while_used() {
+        let cx = Context::new();
+        let dispatch = Dispatch::<Unused>::subscribe_with_context(&cx, |_| {});
+        let other = Dispatch::<Unused>::with_context(&cx);
+        let clone = dispatch.clone();
+        other.set(Unused(1));
+
+        drop(other);
+        drop(dispatch);
+        assert!(cx.try_get::<Unused>().is_some());
+
+        drop(clone);
+        assert!(cx.try_get::<Unused>().is_none());
+        // Bookkeeping for the store is dropped with it.
+        assert!(cx.try_get::<Mrc<Subscribers<Unused>>>().is_none());
+        assert!(!cx.inner.borrow().contains::<Handles<Unused>>());
+    }
+
+    thread_local! {
+        static CREATED: Cell<u32> = Default::default();
+    }
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Persisted(u32);
+    impl Store for Persisted {
+        fn new() -> Self {
+            // Stands in for loading saved state.
+            CREATED.with(|created| created.set(created.get() + 1));
+            Self(1)
+        }
+
+        fn reset(_: &Context) -> Self {
+            Self(0)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    #[test]
+    fn reset_does_not_create_store_again() {
+        let cx = Context::new();
+        assert!(cx.get::<Persisted>().0 == 1);
+
+        cx.reset_all();
+
+        assert!(cx.get::<Persisted>().0 == 0);
+        assert!(CREATED.with(Cell::get) == 1);
+    }
+
+    #[test]
+    fn retained_store_is_kept() {
+        let cx = Context::new();
+        let dispatch = Dispatch::<TestState>::with_context(&cx);
+        dispatch.get();
+
+        drop(dispatch);
+
+        assert!(cx.try_get::<TestState>().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "TestState was notified recursively more than 100 times")]
+    fn update_loop_panics() {
+        let cx = Context::new();
+        let _id = {
+            let cx = cx.clone();
+            cx.clone()
+                .subscribe_silent(move |state: Rc<TestState>| cx.set(TestState(state.0 + 1)))
+        };
+
+        cx.set(TestState(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "tests::TestState was notified recursively more than 5 times")]
+    fn update_loop_across_stores_panics() {
+        let cx = Context::new();
+        cx.set_max_notify_depth(5);
+        let _a = {
+            let cx = cx.clone();
+            cx.clone()
+                .subscribe_silent(move |state: Rc<TestState>| cx.set(TestState2(state.0 + 1)))
+        };
+        let _b = {
+            let cx = cx.clone();
+            cx.clone()
+                .subscribe_silent(move |state: Rc<TestState2>| cx.set(TestState(state.0 + 1)))
+        };
+
+        cx.set(TestState(1));
+    }
+
+    #[test]
+    fn updates_are_counted_separately() {
+        let cx = Context::new();
+        cx.set_max_notify_depth(1);
+        let _id = cx.subscribe_silent(|_: Rc<TestState>| {});
+
+        cx.set(TestState(1));
+        cx.set(TestState(2));
+    }
+
+    #[test]
+    fn wide_updates_are_not_loops() {
+        let cx = Context::new();
+        let _ids = (0..101)
+            .map(|_| {
+                let cx = cx.clone();
+                cx.clone().subscribe_silent(move |_: Rc<TestState>| {
+                    cx.reduce_unchecked(|state: Rc<TestState2>| TestState2(state.0 + 1).into())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        cx.set(TestState(1));
+
+        assert!(cx.get::<TestState2>().0 == 101);
+    }
 }
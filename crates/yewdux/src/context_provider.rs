@@ -0,0 +1,77 @@
+//! Scope stores to a part of the component tree.
+//!
+//! Every component inside a [`YewduxRoot`] gets its stores from that root, instead of the global
+//! context. This way the same store type can have more than one instance on a page.
+//!
+//! ```
+//! use yew::prelude::*;
+//! use yewdux::prelude::*;
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Editor {
+//!     text: String,
+//! }
+//!
+//! #[function_component]
+//! fn EditorView() -> Html {
+//!     let (editor, dispatch) = use_store::<Editor>();
+//!     let oninput = dispatch.reduce_mut_callback_with(|editor, e: InputEvent| {
+//!         editor.text = e.target_unchecked_into::<web_sys::HtmlInputElement>().value();
+//!     });
+//!
+//!     html! {
+//!         <input value={editor.text.clone()} {oninput} />
+//!     }
+//! }
+//!
+//! #[function_component]
+//! fn App() -> Html {
+//!     // Each editor has its own `Editor` store.
+//!     html! {
+//!         <>
+//!         <YewduxRoot>
+//!             <EditorView />
+//!         </YewduxRoot>
+//!         <YewduxRoot>
+//!             <EditorView />
+//!         </YewduxRoot>
+//!         </>
+//!     }
+//! }
+//! ```
+//!
+//! Stores get [listeners](crate::listener) of their own in each root, as long as they initialize
+//! them with [`init_listener_with_context`](crate::listener::init_listener_with_context), as the
+//! `Store` macro does.
+use yew::prelude::*;
+
+use crate::context::Context;
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct YewduxRootProps {
+    #[prop_or_default]
+    pub children: Html,
+    /// Use this context instead of creating a new one. Useful when the stores need to be
+    /// accessed from outside the component tree, e.g. after a server side render. Children
+    /// switch to a new context when this changes.
+    #[prop_or_default]
+    pub context: Option<Context>,
+}
+
+/// Provides a fresh [`Context`] to all of its children.
+///
+/// The context lives as long as this component. This makes it suitable for server side
+/// rendering: wrapping the app in a `YewduxRoot` gives every render its own stores, which are
+/// dropped once rendering is done.
+#[function_component]
+pub fn YewduxRoot(props: &YewduxRootProps) -> Html {
+    let cx = use_memo(props.context.clone(), |context| {
+        context.clone().unwrap_or_default()
+    });
+
+    html! {
+        <ContextProvider<Context> context={(*cx).clone()}>
+            { props.children.clone() }
+        </ContextProvider<Context>>
+    }
+}
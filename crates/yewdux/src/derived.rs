@@ -0,0 +1,296 @@
+//! Stores computed from other stores.
+//!
+//! A derived store is recomputed whenever one of its sources changes. It can be read like any
+//! other store, but cannot be changed by reducers.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use yewdux::{derived::DerivedStore, prelude::*};
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Todos {
+//!     items: Vec<(String, bool)>,
+//! }
+//!
+//! #[derive(Clone, PartialEq, Store)]
+//! #[store(derived)]
+//! struct Remaining(usize);
+//!
+//! impl DerivedStore for Remaining {
+//!     type Sources = (Todos,);
+//!
+//!     fn compute((todos,): (Rc<Todos>,)) -> Self {
+//!         Self(todos.items.iter().filter(|(_, done)| !done).count())
+//!     }
+//! }
+//!
+//! # fn main() {
+//! Dispatch::<Todos>::new().reduce_mut(|todos| todos.items.push(("Write docs".into(), false)));
+//!
+//! assert!(Dispatch::<Remaining>::new().get().0 == 1);
+//! # }
+//! ```
+use std::{any::Any, marker::PhantomData, rc::Rc};
+
+use crate::{context::Context, mrc::Mrc, store::Store};
+
+/// A tuple of stores.
+pub trait Sources: 'static {
+    /// Current state of every store, e.g. `(Rc<A>, Rc<B>)`.
+    type States;
+
+    /// Get the current state of every store.
+    fn get(cx: &Context) -> Self::States;
+
+    /// Call `on_change` whenever any of the stores change. Subscriptions last as long as the
+    /// returned value.
+    fn subscribe<F: Fn() + Clone + 'static>(cx: &Context, on_change: F) -> Vec<Box<dyn Any>>;
+}
+
+/// A function selecting a value from a tuple of stores, e.g. `Fn(&A, &B) -> R` for `(A, B)`.
+pub trait Select<S: Sources, R> {
+    /// Select the value from the current state of every store.
+    fn select(&self, states: &S::States) -> R;
+}
+
+/// Like [`Select`], but also given dependencies, e.g. `Fn(&A, &B, &D) -> R` for `(A, B)`.
+pub trait SelectWithDeps<S: Sources, D, R> {
+    /// Select the value from the current state of every store.
+    fn select(&self, states: &S::States, deps: &D) -> R;
+}
+
+macro_rules! impl_sources {
+    ($($s:ident),+) => {
+        impl<$($s: Store),+> Sources for ($($s,)+) {
+            type States = ($(Rc<$s>,)+);
+
+            fn get(cx: &Context) -> Self::States {
+                ($(cx.get::<$s>(),)+)
+            }
+
+            fn subscribe<CB: Fn() + Clone + 'static>(
+                cx: &Context,
+                on_change: CB,
+            ) -> Vec<Box<dyn Any>> {
+                vec![$({
+                    let on_change = on_change.clone();
+                    let id = cx.subscribe_silent(move |_: Rc<$s>| on_change());
+                    Box::new(id) as Box<dyn Any>
+                }),+]
+            }
+        }
+
+        impl<$($s: Store,)+ Fun, Out> Select<($($s,)+), Out> for Fun
+        where
+            Fun: Fn($(&$s),+) -> Out,
+        {
+            #[allow(non_snake_case)]
+            fn select(&self, ($($s,)+): &($(Rc<$s>,)+)) -> Out {
+                self($(&**$s),+)
+            }
+        }
+
+        impl<$($s: Store,)+ Dep, Fun, Out> SelectWithDeps<($($s,)+), Dep, Out> for Fun
+        where
+            Fun: Fn($(&$s,)+ &Dep) -> Out,
+        {
+            #[allow(non_snake_case)]
+            fn select(&self, ($($s,)+): &($(Rc<$s>,)+), deps: &Dep) -> Out {
+                self($(&**$s,)+ deps)
+            }
+        }
+    };
+}
+
+impl_sources!(A);
+impl_sources!(A, B);
+impl_sources!(A, B, C);
+impl_sources!(A, B, C, D);
+impl_sources!(A, B, C, D, E);
+impl_sources!(A, B, C, D, E, F);
+
+/// A store computed from other stores.
+///
+/// Use `#[store(derived)]` with the `Store` macro to implement [`Store`], or call [`new`] from
+/// [`Store::new_with_context`] when implementing it manually.
+///
+/// Derived stores are read only. Their [`Dispatch`](crate::dispatch::Dispatch) still has every
+/// reducer, but calling one panics. Options that change how a store is created or persisted,
+/// such as `storage`, `listener`, `middleware`, `hydrate` and `snapshot`, don't apply and are
+/// rejected by the macro.
+///
+/// ```compile_fail
+/// # use std::rc::Rc;
+/// # use yewdux::{derived::DerivedStore, prelude::*};
+/// # #[derive(Default, Clone, PartialEq, Store)]
+/// # struct Todos(Vec<String>);
+/// #[derive(Clone, PartialEq, Store)]
+/// #[store(derived, storage = "local")]
+/// struct Count(usize);
+/// # impl DerivedStore for Count {
+/// #     type Sources = (Todos,);
+/// #     fn compute((todos,): (Rc<Todos>,)) -> Self {
+/// #         Self(todos.0.len())
+/// #     }
+/// # }
+/// ```
+pub trait DerivedStore: Store {
+    /// The stores this is computed from.
+    type Sources: Sources;
+
+    /// Compute the value from the current state of every source.
+    fn compute(sources: <Self::Sources as Sources>::States) -> Self;
+}
+
+/// Holds the subscriptions to the sources of `S`.
+struct Subscriptions<S> {
+    _ids: Vec<Box<dyn Any>>,
+    _store_type: PhantomData<S>,
+}
+impl<S: DerivedStore> Store for Mrc<Subscriptions<S>> {
+    fn new() -> Self {
+        Subscriptions {
+            _ids: Vec::new(),
+            _store_type: PhantomData,
+        }
+        .into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+/// Compute `S` from the current state of its sources.
+pub fn compute<S: DerivedStore>(cx: &Context) -> S {
+    S::compute(S::Sources::get(cx))
+}
+
+/// Create `S` in the given context, recomputing it whenever its sources change.
+pub fn new<S: DerivedStore>(cx: &Context) -> S {
+    cx.set_read_only::<S>();
+
+    let on_change = {
+        // The context holds these subscriptions, so they mustn't keep it alive.
+        let cx = cx.downgrade();
+        move || {
+            if let Some(cx) = cx.upgrade() {
+                // The store may have been dropped since, see `Store::retain`.
+                if cx.try_get::<S>().is_none() {
+                    return;
+                }
+                let value = compute::<S>(&cx);
+                cx.reduce_unchecked(|_| Rc::new(value));
+            }
+        }
+    };
+    let ids = S::Sources::subscribe(cx, on_change);
+    cx.init(Mrc::new(Subscriptions::<S> {
+        _ids: ids,
+        _store_type: PhantomData,
+    }));
+
+    compute(cx)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::{
+        dispatch::{self, Dispatch},
+        test_util::number_stores,
+    };
+
+    use super::*;
+
+    number_stores!(Price = 2, Quantity = 3);
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Total(u32);
+    impl Store for Total {
+        fn new() -> Self {
+            compute(&Context::global())
+        }
+
+        fn new_with_context(cx: &Context) -> Self {
+            new(cx)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+    impl DerivedStore for Total {
+        type Sources = (Price, Quantity);
+
+        fn compute((price, quantity): (Rc<Price>, Rc<Quantity>)) -> Self {
+            Self(price.0 * quantity.0)
+        }
+    }
+
+    #[test]
+    fn derived_is_computed_from_sources() {
+        assert!(dispatch::get::<Total>().0 == 6);
+    }
+
+    #[test]
+    fn derived_is_recomputed_when_sources_change() {
+        dispatch::get::<Total>();
+
+        dispatch::set(Price(3));
+        assert!(dispatch::get::<Total>().0 == 9);
+
+        dispatch::set(Quantity(1));
+        assert!(dispatch::get::<Total>().0 == 3);
+    }
+
+    #[test]
+    fn derived_notifies_only_when_changed() {
+        let calls = Rc::new(Cell::new(0));
+        let _dispatch = {
+            let calls = calls.clone();
+            Dispatch::<Total>::subscribe_silent(move |_| calls.set(calls.get() + 1))
+        };
+
+        // 3 * 2 == 2 * 3
+        dispatch::batch(|| {
+            dispatch::set(Price(3));
+            dispatch::set(Quantity(2));
+        });
+        assert!(calls.get() == 0);
+
+        dispatch::set(Quantity(3));
+        assert!(calls.get() == 1);
+    }
+
+    #[test]
+    fn derived_uses_sources_from_same_context() {
+        let cx = Context::new();
+        cx.set(Price(10));
+
+        assert!(cx.get::<Total>().0 == 30);
+        assert!(dispatch::get::<Total>().0 == 6);
+    }
+
+    #[test]
+    fn selects_from_sources() {
+        let cx = Context::new();
+        cx.set(Price(5));
+        let states = <(Price, Quantity)>::get(&cx);
+
+        let total = |price: &Price, quantity: &Quantity| price.0 * quantity.0;
+        assert!(Select::<(Price, Quantity), _>::select(&total, &states) == 15);
+
+        let discounted =
+            |price: &Price, quantity: &Quantity, discount: &u32| price.0 * quantity.0 - discount;
+        assert!(SelectWithDeps::<(Price, Quantity), _, _>::select(&discounted, &states, &5) == 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "read only")]
+    fn derived_cannot_be_reduced() {
+        dispatch::set(Total(1));
+    }
+}
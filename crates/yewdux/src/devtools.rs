@@ -0,0 +1,351 @@
+//! Debug stores with the [Redux DevTools](https://github.com/reduxjs/redux-devtools) browser
+//! extension. Requires the `devtools` feature.
+//!
+//! Every change is sent to the extension, labeled with the store and reducer that made it, along
+//! with the state of every store included in snapshots (see [`registry`](crate::registry)).
+//! Jumping to a previous state, or importing one, applies it to those stores.
+//!
+//! ```ignore
+//! use yewdux::devtools::{self, Extension};
+//!
+//! if let Some(extension) = Extension::connect("my app") {
+//!     devtools::connect(extension);
+//! }
+//! ```
+//!
+//! The extension is reached through a [`Transport`], so the bridge can be used with anything that
+//! speaks the same protocol.
+use std::{cell::Cell, rc::Rc};
+
+use serde_json::{json, Value};
+
+use crate::{
+    context::{Context, WeakContext},
+    registry::Snapshot,
+};
+
+/// Carries messages between the bridge and the extension. Messages are JSON, in the format used by
+/// the extension's `connect` API.
+pub trait Transport: 'static {
+    /// Send the initial state.
+    fn init(&self, state: &Value);
+
+    /// Send an action, along with the state after it was applied.
+    fn send(&self, action: &Value, state: &Value);
+
+    /// Call `on_message` with every message received from the extension.
+    fn subscribe(&self, on_message: Box<dyn Fn(Value)>);
+}
+
+struct Bridge {
+    transport: Box<dyn Transport>,
+    /// Set while applying state from the extension, so it isn't sent back.
+    applying: Cell<bool>,
+}
+
+impl Bridge {
+    fn receive(&self, cx: &Context, message: Value) {
+        if message["type"] != "DISPATCH" {
+            return;
+        }
+
+        let state = match message["payload"]["type"].as_str() {
+            Some("JUMP_TO_STATE") | Some("JUMP_TO_ACTION") => {
+                match message["state"].as_str().map(serde_json::from_str) {
+                    Some(Ok(state)) => state,
+                    Some(Err(err)) => {
+                        crate::log::error!("Unable to parse state from devtools: {:?}", err);
+                        return;
+                    }
+                    None => return,
+                }
+            }
+            Some("IMPORT_STATE") => {
+                let lifted = &message["payload"]["nextLiftedState"];
+                let index = match lifted["currentStateIndex"].as_u64() {
+                    Some(index) => index as usize,
+                    None => return,
+                };
+                lifted["computedStates"][index]["state"].clone()
+            }
+            _ => return,
+        };
+
+        let snapshot: Snapshot = match serde_json::from_value(state) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                crate::log::error!("Unable to read state from devtools: {:?}", err);
+                return;
+            }
+        };
+
+        self.applying.set(true);
+        let result = cx.restore_all(&snapshot);
+        self.applying.set(false);
+
+        if let Err(err) = result {
+            crate::log::error!("Unable to apply state from devtools: {:?}", err);
+        }
+    }
+}
+
+fn state(cx: &Context) -> Value {
+    serde_json::to_value(cx.snapshot_all()).unwrap_or_default()
+}
+
+/// Send every change in the global context to the extension.
+pub fn connect<T: Transport>(transport: T) {
+    connect_with_context(&Context::global(), transport)
+}
+
+/// Send every change in the given context to the extension.
+pub fn connect_with_context<T: Transport>(cx: &Context, transport: T) {
+    let bridge = Rc::new(Bridge {
+        transport: Box::new(transport),
+        applying: Cell::new(false),
+    });
+
+    bridge.transport.init(&state(cx));
+
+    {
+        // The context holds on to the bridge, so the bridge mustn't keep it alive.
+        let cx: WeakContext = cx.downgrade();
+        let receiver = Rc::downgrade(&bridge);
+        bridge.transport.subscribe(Box::new(move |message| {
+            if let (Some(cx), Some(bridge)) = (cx.upgrade(), receiver.upgrade()) {
+                bridge.receive(&cx, message);
+            }
+        }));
+    }
+
+    cx.observe(move |cx, store, reducer| {
+        if bridge.applying.get() {
+            return;
+        }
+
+        let action = json!({ "type": store, "reducer": reducer });
+        bridge.transport.send(&action, &state(cx));
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use extension::Extension;
+
+#[cfg(target_arch = "wasm32")]
+mod extension {
+    use serde_json::Value;
+    use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+    use web_sys::js_sys::{Array, Function, Reflect, JSON};
+
+    use super::Transport;
+
+    /// A connection to the browser extension.
+    pub struct Extension {
+        connection: JsValue,
+    }
+
+    impl Extension {
+        /// Connect to the extension, showing up under the given name. Returns `None` if the
+        /// extension isn't installed.
+        pub fn connect(name: &str) -> Option<Self> {
+            let window = web_sys::window()?;
+            let extension = Reflect::get(&window, &"__REDUX_DEVTOOLS_EXTENSION__".into()).ok()?;
+            if extension.is_undefined() {
+                return None;
+            }
+
+            let connect: Function = Reflect::get(&extension, &"connect".into())
+                .ok()?
+                .dyn_into()
+                .ok()?;
+            let options = to_js(&serde_json::json!({ "name": name }));
+            let connection = connect.call1(&extension, &options).ok()?;
+
+            Some(Self { connection })
+        }
+
+        fn call(&self, method: &str, args: &Array) {
+            let result = Reflect::get(&self.connection, &method.into())
+                .and_then(|function| function.dyn_into::<Function>())
+                .and_then(|function| function.apply(&self.connection, args));
+
+            if let Err(err) = result {
+                crate::log::error!("Unable to call devtools {}: {:?}", method, err);
+            }
+        }
+    }
+
+    fn to_js(value: &Value) -> JsValue {
+        JSON::parse(&value.to_string()).unwrap_or(JsValue::NULL)
+    }
+
+    fn from_js(value: &JsValue) -> Option<Value> {
+        let json = String::from(JSON::stringify(value).ok()?);
+        serde_json::from_str(&json).ok()
+    }
+
+    impl Transport for Extension {
+        fn init(&self, state: &Value) {
+            self.call("init", &Array::of1(&to_js(state)));
+        }
+
+        fn send(&self, action: &Value, state: &Value) {
+            self.call("send", &Array::of2(&to_js(action), &to_js(state)));
+        }
+
+        fn subscribe(&self, on_message: Box<dyn Fn(Value)>) {
+            let closure = Closure::wrap(Box::new(move |message: JsValue| {
+                if let Some(message) = from_js(&message) {
+                    on_message(message);
+                }
+            }) as Box<dyn Fn(JsValue)>);
+
+            self.call("subscribe", &Array::of1(closure.as_ref()));
+            // The extension holds on to it for as long as the page is open.
+            closure.forget();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        registry,
+        store::{Named, Store},
+    };
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct Counter(u32);
+    impl Named for Counter {
+        const NAME: &'static str = "counter";
+    }
+    impl Store for Counter {
+        fn new() -> Self {
+            registry::register::<Self>();
+            Self(0)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    type OnMessage = Box<dyn Fn(Value)>;
+
+    /// An in-memory stand-in for the extension.
+    #[derive(Clone, Default)]
+    struct FakeExtension {
+        init: Rc<RefCell<Option<Value>>>,
+        sent: Rc<RefCell<Vec<(Value, Value)>>>,
+        on_message: Rc<RefCell<Option<OnMessage>>>,
+    }
+
+    impl FakeExtension {
+        fn dispatch(&self, message: Value) {
+            let on_message = self.on_message.borrow();
+            (on_message.as_ref().expect("not subscribed"))(message);
+        }
+    }
+
+    impl Transport for FakeExtension {
+        fn init(&self, state: &Value) {
+            *self.init.borrow_mut() = Some(state.clone());
+        }
+
+        fn send(&self, action: &Value, state: &Value) {
+            self.sent.borrow_mut().push((action.clone(), state.clone()));
+        }
+
+        fn subscribe(&self, on_message: Box<dyn Fn(Value)>) {
+            *self.on_message.borrow_mut() = Some(on_message);
+        }
+    }
+
+    fn setup() -> (Context, FakeExtension) {
+        let cx = Context::new();
+        cx.set(Counter(1));
+        let extension = FakeExtension::default();
+        connect_with_context(&cx, extension.clone());
+
+        (cx, extension)
+    }
+
+    fn counter_state(count: u32) -> Value {
+        json!({ "counter": count })
+    }
+
+    #[test]
+    fn sends_initial_state() {
+        let (_cx, extension) = setup();
+
+        assert_eq!(*extension.init.borrow(), Some(counter_state(1)));
+    }
+
+    #[test]
+    fn sends_changes() {
+        let (cx, extension) = setup();
+
+        cx.set(Counter(2));
+
+        let sent = extension.sent.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0["type"], std::any::type_name::<Counter>());
+        assert_eq!(sent[0].0["reducer"], "set");
+        assert_eq!(sent[0].1, counter_state(2));
+    }
+
+    #[test]
+    fn jumps_to_state() {
+        let (cx, extension) = setup();
+
+        extension.dispatch(json!({
+            "type": "DISPATCH",
+            "payload": { "type": "JUMP_TO_STATE" },
+            "state": counter_state(5).to_string(),
+        }));
+
+        assert!(cx.get::<Counter>().0 == 5);
+        // Applying state from the extension isn't sent back.
+        assert!(extension.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn imports_state() {
+        let (cx, extension) = setup();
+
+        extension.dispatch(json!({
+            "type": "DISPATCH",
+            "payload": {
+                "type": "IMPORT_STATE",
+                "nextLiftedState": {
+                    "currentStateIndex": 1,
+                    "computedStates": [
+                        { "state": counter_state(3) },
+                        { "state": counter_state(4) },
+                    ],
+                },
+            },
+        }));
+
+        assert!(cx.get::<Counter>().0 == 4);
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        let (cx, extension) = setup();
+
+        extension.dispatch(json!({ "type": "START" }));
+        extension.dispatch(json!({
+            "type": "DISPATCH",
+            "payload": { "type": "COMMIT" },
+        }));
+
+        assert!(cx.get::<Counter>().0 == 1);
+    }
+}
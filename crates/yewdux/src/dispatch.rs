@@ -25,26 +25,64 @@ use std::{future::Future, pin::Pin};
 
 use yew::Callback;
 
+#[cfg(feature = "future")]
+use crate::{
+    conflict::{CheckedError, OnConflict},
+    task::{Policy, PolicyDispatch},
+};
 use crate::{
-    context,
-    mrc::Mrc,
+    context::{Context, Handle},
+    effect::EffectReducer,
     store::{AsyncReducer, Reducer, Store},
-    subscriber::{Callable, SubscriberId, Subscribers},
+    subscriber::{Callable, SubscriberId},
+    transaction::Transaction,
 };
 
 /// The primary interface to a [`Store`].
 pub struct Dispatch<S: Store> {
     _subscriber_id: Option<Rc<SubscriberId<S>>>,
+    _handle: Rc<Handle<S>>,
+    cx: Context,
 }
 
 impl<S: Store> Dispatch<S> {
-    /// Create a new dispatch.
+    /// Create a new dispatch for the global context.
     pub fn new() -> Self {
+        Self::with_context(&Context::global())
+    }
+
+    /// Create a new dispatch for the given context.
+    ///
+    /// ```
+    /// use yewdux::{prelude::*, Context};
+    ///
+    /// #[derive(Default, Clone, PartialEq, Store)]
+    /// struct State {
+    ///     count: usize,
+    /// }
+    ///
+    /// # fn main() {
+    /// let cx = Context::new();
+    /// let dispatch = Dispatch::<State>::with_context(&cx);
+    /// dispatch.reduce_mut(|state| state.count = 1);
+    ///
+    /// // The global store is untouched.
+    /// assert!(Dispatch::<State>::new().get().count == 0);
+    /// # }
+    /// ```
+    pub fn with_context(cx: &Context) -> Self {
         Self {
             _subscriber_id: Default::default(),
+            _handle: Rc::new(Handle::new(cx)),
+            cx: cx.clone(),
         }
     }
 
+    /// The context this dispatch reads from and writes to.
+    pub fn context(&self) -> &Context {
+        &self.cx
+    }
+
     /// Create a dispatch that subscribes to changes in state. Latest state is sent immediately,
     /// and on every subsequent change. Automatically unsubscribes when this dispatch is dropped.
     /// ```
@@ -99,10 +137,17 @@ impl<S: Store> Dispatch<S> {
     /// }
     /// ```
     pub fn subscribe<C: Callable<S>>(on_change: C) -> Self {
-        let id = subscribe(on_change);
+        Self::subscribe_with_context(&Context::global(), on_change)
+    }
+
+    /// Similar to [Self::subscribe], but subscribes to the store in the given context.
+    pub fn subscribe_with_context<C: Callable<S>>(cx: &Context, on_change: C) -> Self {
+        let id = cx.subscribe(on_change);
 
         Self {
             _subscriber_id: Some(Rc::new(id)),
+            _handle: Rc::new(Handle::new(cx)),
+            cx: cx.clone(),
         }
     }
 
@@ -110,16 +155,85 @@ impl<S: Store> Dispatch<S> {
     /// however state is **not** sent immediately. Automatically unsubscribes when this dispatch is
     /// dropped.
     pub fn subscribe_silent<C: Callable<S>>(on_change: C) -> Self {
-        let id = subscribe_silent(on_change);
+        Self::subscribe_silent_with_context(&Context::global(), on_change)
+    }
+
+    /// Similar to [Self::subscribe_silent], but subscribes to the store in the given context.
+    pub fn subscribe_silent_with_context<C: Callable<S>>(cx: &Context, on_change: C) -> Self {
+        // Make sure the store exists, so derived stores start tracking their sources.
+        cx.get_or_init::<S>();
+        let id = cx.subscribe_silent(on_change);
 
         Self {
             _subscriber_id: Some(Rc::new(id)),
+            _handle: Rc::new(Handle::new(cx)),
+            cx: cx.clone(),
         }
     }
 
     /// Get the current state.
     pub fn get(&self) -> Rc<S> {
-        get::<S>()
+        self.cx.get::<S>()
+    }
+
+    /// Reset state to its initial value, from [`Store::reset`]. Subscribers are notified if it
+    /// changed.
+    ///
+    /// ```
+    /// # use yewdux::prelude::*;
+    /// # #[derive(Default, Clone, PartialEq, Eq, Store)]
+    /// # struct State {
+    /// #     count: u32,
+    /// # }
+    /// # fn main() {
+    /// let dispatch = Dispatch::<State>::new();
+    /// dispatch.reduce_mut(|state| state.count = 1);
+    ///
+    /// dispatch.reset();
+    /// assert!(dispatch.get().count == 0);
+    /// # }
+    /// ```
+    pub fn reset(&self) {
+        self.cx.reset::<S>();
+    }
+
+    /// Make several changes, notifying subscribers only once they are all done. Every store in
+    /// this dispatch's context is batched, so changes to other stores are collapsed as well.
+    /// Batches can be nested, notifications are sent when the outermost batch is done.
+    ///
+    /// ```
+    /// # use yew::prelude::*;
+    /// # use yewdux::prelude::*;
+    /// # #[derive(Default, Clone, PartialEq, Eq, Store)]
+    /// # struct State {
+    /// #     count: u32,
+    /// # }
+    /// # fn main() {
+    /// # let dispatch = Dispatch::<State>::new();
+    /// dispatch.batch(|dispatch| {
+    ///     for _ in 0..5 {
+    ///         dispatch.reduce_mut(|state| state.count += 1);
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn batch<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Self) -> R,
+    {
+        self.cx.batch(|| f(self))
+    }
+
+    /// Change any number of stores in this dispatch's context at once. If `f` returns an error,
+    /// every store it changed is rolled back and no subscribers are notified. Otherwise
+    /// subscribers are notified once all changes are applied.
+    ///
+    /// See the [transaction](crate::transaction) module for an example.
+    pub fn transaction<F, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&Transaction) -> Result<T, E>,
+    {
+        self.cx.transaction(f)
     }
 
     /// Apply a [`Reducer`](crate::store::Reducer) immediately.
@@ -150,7 +264,7 @@ impl<S: Store> Dispatch<S> {
     /// # }
     /// ```
     pub fn apply<R: Reducer<S>>(&self, reducer: R) {
-        reduce(reducer);
+        self.cx.apply(reducer);
     }
 
     /// Apply an [`AsyncReducer`](crate::store::AsyncReducer) immediately.
@@ -188,7 +302,7 @@ impl<S: Store> Dispatch<S> {
     /// ```
     #[cfg(feature = "future")]
     pub async fn apply_future<R: AsyncReducer<S>>(&self, reducer: R) {
-        reduce_future(reducer).await;
+        self.cx.reduce_future(reducer).await;
     }
 
     /// Create a callback that applies a [`Reducer`](crate::store::Reducer).
@@ -226,12 +340,28 @@ impl<S: Store> Dispatch<S> {
         M: Reducer<S>,
         F: Fn(E) -> M + 'static,
     {
+        let cx = self.cx.clone();
         Callback::from(move |e| {
-            let msg = f(e);
-            reduce(msg);
+            cx.apply(f(e));
         })
     }
 
+    /// Apply an [`EffectReducer`], running its effects once subscribers are notified. See the
+    /// [effect](crate::effect) module.
+    pub fn apply_effect<R: EffectReducer<S>>(&self, reducer: R) {
+        self.cx.reduce_effect(reducer);
+    }
+
+    /// Create a callback for applying an [`EffectReducer`].
+    pub fn apply_effect_callback<E, M, F>(&self, f: F) -> Callback<E>
+    where
+        M: EffectReducer<S>,
+        F: Fn(E) -> M + 'static,
+    {
+        let cx = self.cx.clone();
+        Callback::from(move |e| cx.reduce_effect(f(e)))
+    }
+
     /// Create a callback for applying an [`AsyncReducer`](crate::store::AsyncReducer).
     ///
     /// ```
@@ -274,14 +404,43 @@ impl<S: Store> Dispatch<S> {
         M: AsyncReducer<S> + 'static,
         F: Fn(E) -> M + 'static,
     {
+        let cx = self.cx.clone();
         Callback::from(move |e| {
+            let cx = cx.clone();
             let msg = f(e);
             yew::platform::spawn_local(async move {
-                reduce_future(msg).await;
+                cx.reduce_future(msg).await;
             })
         })
     }
 
+    /// Run async reducers with a [`Policy`](crate::task::Policy), which decides what happens
+    /// when they overlap with others started with the same key. Each returns a handle to cancel
+    /// it. See the [task](crate::task) module.
+    ///
+    /// ```
+    /// # use std::rc::Rc;
+    /// # use yewdux::{prelude::*, task::Policy};
+    /// # #[derive(Default, Clone, PartialEq, Store)]
+    /// # struct State {
+    /// #     count: u32,
+    /// # }
+    /// # async fn run() {
+    /// let dispatch = Dispatch::<State>::new();
+    /// let future = dispatch
+    ///     .with_policy("count", Policy::LatestWins)
+    ///     .reduce_future(|state| async move { State { count: state.count + 1 }.into() });
+    /// let task = future.task();
+    ///
+    /// // Elsewhere, maybe when the component is destroyed.
+    /// task.cancel();
+    /// # }
+    /// ```
+    #[cfg(feature = "future")]
+    pub fn with_policy(&self, key: &'static str, policy: Policy) -> PolicyDispatch<S> {
+        PolicyDispatch::new(&self.cx, key, policy)
+    }
+
     /// Set state to given value immediately.
     ///
     /// ```
@@ -297,7 +456,7 @@ impl<S: Store> Dispatch<S> {
     /// # }
     /// ```
     pub fn set(&self, val: S) {
-        set(val);
+        self.cx.set(val);
     }
 
     /// Set state using value from callback.
@@ -325,9 +484,10 @@ impl<S: Store> Dispatch<S> {
     where
         F: Fn(E) -> S + 'static,
     {
+        let cx = self.cx.clone();
         Callback::from(move |e| {
             let val = f(e);
-            set(val);
+            cx.set(val);
         })
     }
 
@@ -349,7 +509,7 @@ impl<S: Store> Dispatch<S> {
     where
         F: FnOnce(Rc<S>) -> Rc<S>,
     {
-        reduce(f);
+        self.cx.reduce(f);
     }
 
     /// Change state immediately, in an async context.
@@ -384,7 +544,7 @@ impl<S: Store> Dispatch<S> {
         FUT: Future<Output = Rc<S>>,
         FUN: FnOnce(Rc<S>) -> FUT,
     {
-        reduce_future(f).await;
+        self.cx.reduce_future(f).await;
     }
 
     /// Create a callback that changes state.
@@ -410,8 +570,9 @@ impl<S: Store> Dispatch<S> {
         F: Fn(Rc<S>) -> Rc<S> + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         Callback::from(move |_| {
-            reduce(&f);
+            cx.reduce(&f);
         })
     }
 
@@ -449,11 +610,13 @@ impl<S: Store> Dispatch<S> {
         FUN: Fn(Rc<S>) -> FUT + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         let f = Rc::new(f);
         Callback::from(move |_| {
             let f = f.clone();
+            let cx = cx.clone();
             yew::platform::spawn_local(async move {
-                reduce_future(f.as_ref()).await;
+                cx.reduce_future(f.as_ref()).await;
             })
         })
     }
@@ -487,8 +650,9 @@ impl<S: Store> Dispatch<S> {
         F: Fn(Rc<S>, E) -> Rc<S> + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         Callback::from(move |e: E| {
-            reduce(|x| f(x, e));
+            cx.reduce(|x| f(x, e));
         })
     }
 
@@ -528,11 +692,13 @@ impl<S: Store> Dispatch<S> {
         FUN: Fn(Rc<S>, E) -> FUT + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         let f = Rc::new(f);
         Callback::from(move |e: E| {
             let f = f.clone();
+            let cx = cx.clone();
             yew::platform::spawn_local(async move {
-                reduce_future(move |s| f(s, e)).await;
+                cx.reduce_future(move |s| f(s, e)).await;
             })
         })
     }
@@ -557,8 +723,8 @@ impl<S: Store> Dispatch<S> {
         F: FnOnce(&mut S) -> R,
     {
         let mut result = None;
-        
-        reduce_mut(|x| {
+
+        self.cx.reduce_mut(|x| {
             result = Some(f(x));
         });
 
@@ -595,7 +761,57 @@ impl<S: Store> Dispatch<S> {
         S: Clone,
         F: FnOnce(&mut S) -> Pin<Box<dyn Future<Output = R> + '_>>,
     {
-        reduce_mut_future(f).await;
+        self.cx.reduce_mut_future(f).await;
+    }
+
+    /// Change state from a future, detecting changes made to the state while it runs. These would
+    /// otherwise be lost, and are instead handled with `on_conflict`. See the
+    /// [conflict](crate::conflict) module.
+    ///
+    /// The reducer may be run more than once when retrying, so it takes `Fn`. Returns an error if
+    /// the conflict couldn't be resolved, or the change was vetoed by middleware.
+    #[cfg(feature = "future")]
+    pub async fn reduce_future_checked<FUT, FUN>(
+        &self,
+        on_conflict: OnConflict<S>,
+        f: FUN,
+    ) -> Result<(), CheckedError>
+    where
+        FUT: Future<Output = Rc<S>>,
+        FUN: Fn(Rc<S>) -> FUT,
+    {
+        self.cx.reduce_future_checked(on_conflict, f).await
+    }
+
+    /// Run a future with the current state, then mutate the state as it is once the future is
+    /// done. Unlike [Self::reduce_mut_future], `&mut S` is only held once the future is done, so
+    /// changes made in the meantime are kept.
+    ///
+    /// ```
+    /// # use yewdux::prelude::*;
+    /// # #[derive(Default, Clone, PartialEq, Eq, Store)]
+    /// # struct State {
+    /// #     count: u32,
+    /// # }
+    /// # async fn get_incr() -> u32 {
+    /// #   1
+    /// # }
+    /// # async fn do_thing() {
+    /// # let dispatch = Dispatch::<State>::new();
+    /// dispatch
+    ///     .reduce_mut_future_then(|_| get_incr(), |state, incr| state.count += incr)
+    ///     .await;
+    /// # }
+    /// ```
+    #[cfg(feature = "future")]
+    pub async fn reduce_mut_future_then<T, FUT, FUN, M>(&self, f: FUN, then: M)
+    where
+        S: Clone,
+        FUT: Future<Output = T>,
+        FUN: FnOnce(Rc<S>) -> FUT,
+        M: FnOnce(&mut S, T),
+    {
+        self.cx.reduce_mut_future_then(f, then).await;
     }
 
     /// Like [Self::reduce_mut] but from a callback.
@@ -622,8 +838,9 @@ impl<S: Store> Dispatch<S> {
         F: Fn(&mut S) -> R + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         Callback::from(move |_| {
-            reduce_mut(|x| {
+            cx.reduce_mut(|x| {
                 f(x);
             });
         })
@@ -661,11 +878,13 @@ impl<S: Store> Dispatch<S> {
         F: Fn(&mut S) -> Pin<Box<dyn Future<Output = R> + '_>> + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         let f = Rc::new(f);
         Callback::from(move |_| {
             let f = f.clone();
+            let cx = cx.clone();
             yew::platform::spawn_local(async move {
-                reduce_mut_future(f.as_ref()).await;
+                cx.reduce_mut_future(f.as_ref()).await;
             })
         })
     }
@@ -697,8 +916,9 @@ impl<S: Store> Dispatch<S> {
         F: Fn(&mut S, E) -> R + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         Callback::from(move |e: E| {
-            reduce_mut(|x| {
+            cx.reduce_mut(|x| {
                 f(x, e);
             });
         })
@@ -741,11 +961,13 @@ impl<S: Store> Dispatch<S> {
         F: Fn(&mut S, E) -> Pin<Box<dyn Future<Output = R> + '_>> + 'static,
         E: 'static,
     {
+        let cx = self.cx.clone();
         let f = Rc::new(f);
         Callback::from(move |e: E| {
             let f = f.clone();
+            let cx = cx.clone();
             yew::platform::spawn_local(async move {
-                reduce_mut_future(move |s| f(s, e)).await;
+                cx.reduce_mut_future(move |s| f(s, e)).await;
             })
         })
     }
@@ -761,6 +983,8 @@ impl<S: Store> Clone for Dispatch<S> {
     fn clone(&self) -> Self {
         Self {
             _subscriber_id: self._subscriber_id.clone(),
+            _handle: Rc::clone(&self._handle),
+            cx: self.cx.clone(),
         }
     }
 }
@@ -774,15 +998,22 @@ impl<S: Store> PartialEq for Dispatch<S> {
     }
 }
 
+/// Make several changes to global state, notifying subscribers only once they are all done.
+pub fn batch<F: FnOnce() -> R, R>(f: F) -> R {
+    Context::global().batch(f)
+}
+
+/// Change any number of global stores at once, rolling all of them back if `f` returns an error.
+pub fn transaction<F, T, E>(f: F) -> Result<T, E>
+where
+    F: FnOnce(&Transaction) -> Result<T, E>,
+{
+    Context::global().transaction(f)
+}
+
 /// Change state from a function.
 pub fn reduce<S: Store, R: Reducer<S>>(r: R) {
-    let context = context::get_or_init::<S>();
-    let should_notify = context.reduce(r);
-
-    if should_notify {
-        let state = Rc::clone(&context.store.borrow());
-        notify_subscribers(state)
-    }
+    Context::global().reduce(r);
 }
 
 #[cfg(feature = "future")]
@@ -791,21 +1022,12 @@ where
     S: Store,
     R: AsyncReducer<S>,
 {
-    let context = context::get_or_init::<S>();
-    let should_notify = context.reduce_future(r).await;
-
-    if should_notify {
-        let state = Rc::clone(&context.store.borrow());
-        notify_subscribers(state)
-    }
+    Context::global().reduce_future(r).await;
 }
 
 /// Change state using a mutable reference from a function.
 pub fn reduce_mut<S: Store + Clone, F: FnOnce(&mut S)>(f: F) {
-    reduce(|mut state| {
-        f(Rc::make_mut(&mut state));
-        state
-    });
+    Context::global().reduce_mut(f);
 }
 
 #[cfg(feature = "future")]
@@ -814,52 +1036,51 @@ where
     S: Store + Clone,
     F: FnOnce(&mut S) -> Pin<Box<dyn Future<Output = R> + '_>>,
 {
-    reduce_future(|mut state| async move {
-        f(Rc::make_mut(&mut state)).await;
-        state
-    })
-    .await;
+    Context::global().reduce_mut_future(f).await;
 }
 
 /// Set state to given value.
 pub fn set<S: Store>(value: S) {
-    reduce(move |_| value.into());
+    Context::global().set(value);
 }
 
 /// Get current state.
 pub fn get<S: Store>() -> Rc<S> {
-    Rc::clone(&context::get_or_init::<S>().store.borrow())
+    Context::global().get()
+}
+
+/// Reset state to its initial value, from [`Store::reset`].
+pub fn reset<S: Store>() {
+    Context::global().reset::<S>();
+}
+
+/// Reset every store in the global context to its initial value. Subscriptions are kept, and
+/// subscribers are notified once every store is reset.
+///
+/// Useful for clearing user data on logout, or between tests running on the same thread.
+pub fn reset_all() {
+    Context::global().reset_all();
 }
 
 /// Send state to all subscribers.
 pub fn notify_subscribers<S: Store>(state: Rc<S>) {
-    let context = context::get_or_init::<Mrc<Subscribers<S>>>();
-    context.store.borrow().notify(state);
+    Context::global().notify_subscribers(state);
 }
 
 /// Subscribe to a store. `on_change` is called immediately, then every  time state changes.
 pub fn subscribe<S: Store, N: Callable<S>>(on_change: N) -> SubscriberId<S> {
-    // Notify subscriber with inital state.
-    on_change.call(get::<S>());
-
-    context::get_or_init::<Mrc<Subscribers<S>>>()
-        .store
-        .borrow()
-        .subscribe(on_change)
+    Context::global().subscribe(on_change)
 }
 
 /// Similar to [subscribe], however state is not called immediately.
 pub fn subscribe_silent<S: Store, N: Callable<S>>(on_change: N) -> SubscriberId<S> {
-    context::get_or_init::<Mrc<Subscribers<S>>>()
-        .store
-        .borrow()
-        .subscribe(on_change)
+    Context::global().subscribe_silent(on_change)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::mrc::Mrc;
+    use crate::{mrc::Mrc, subscriber::Subscribers};
 
     use super::*;
 
@@ -1242,7 +1463,7 @@ mod tests {
 
     #[test]
     fn dispatch_unsubscribes_when_dropped() {
-        let context = context::get_or_init::<Mrc<Subscribers<TestState>>>();
+        let context = Context::global().get_or_init_internal::<Mrc<Subscribers<TestState>>>();
 
         assert!(context.store.borrow().borrow().0.is_empty());
 
@@ -1257,7 +1478,7 @@ mod tests {
 
     #[test]
     fn dispatch_clone_and_original_unsubscribe_when_both_dropped() {
-        let context = context::get_or_init::<Mrc<Subscribers<TestState>>>();
+        let context = Context::global().get_or_init_internal::<Mrc<Subscribers<TestState>>>();
 
         assert!(context.store.borrow().borrow().0.is_empty());
 
@@ -1274,4 +1495,129 @@ mod tests {
 
         assert!(context.store.borrow().borrow().0.is_empty());
     }
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct TestState2(u32);
+    impl Store for TestState2 {
+        fn new() -> Self {
+            Self(0)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    #[test]
+    fn batch_notifies_once_with_final_state() {
+        let seen = Mrc::new(Vec::new());
+        let dispatch = {
+            let seen = seen.clone();
+            Dispatch::<TestState>::subscribe_silent(move |state: Rc<TestState>| {
+                seen.borrow_mut().push(state.0)
+            })
+        };
+
+        dispatch.batch(|dispatch| {
+            for _ in 0..5 {
+                dispatch.reduce_mut(|state| state.0 += 1);
+            }
+
+            assert!(seen.borrow().is_empty());
+        });
+
+        assert_eq!(*seen.borrow(), vec![5]);
+    }
+
+    #[test]
+    fn nested_batch_notifies_when_outermost_is_done() {
+        let seen = Mrc::new(Vec::new());
+        let _id = {
+            let seen = seen.clone();
+            subscribe_silent(move |state: Rc<TestState>| seen.borrow_mut().push(state.0))
+        };
+
+        batch(|| {
+            reduce_mut(|state: &mut TestState| state.0 += 1);
+            batch(|| reduce_mut(|state: &mut TestState| state.0 += 1));
+
+            assert!(seen.borrow().is_empty());
+        });
+
+        assert_eq!(*seen.borrow(), vec![2]);
+    }
+
+    #[test]
+    fn batch_spans_multiple_stores() {
+        let seen = Mrc::new(Vec::new());
+        let _id1 = {
+            let seen = seen.clone();
+            subscribe_silent(move |state: Rc<TestState>| seen.borrow_mut().push(state.0))
+        };
+        let _id2 = {
+            let seen = seen.clone();
+            subscribe_silent(move |state: Rc<TestState2>| seen.borrow_mut().push(state.0 * 10))
+        };
+
+        batch(|| {
+            reduce_mut(|state: &mut TestState| state.0 += 1);
+            reduce_mut(|state: &mut TestState2| state.0 += 1);
+            reduce_mut(|state: &mut TestState| state.0 += 1);
+        });
+
+        assert_eq!(*seen.borrow(), vec![2, 10]);
+    }
+
+    #[test]
+    fn batch_does_not_notify_when_state_is_unchanged_overall() {
+        let seen = Mrc::new(Vec::new());
+        let _id = {
+            let seen = seen.clone();
+            subscribe_silent(move |state: Rc<TestState>| seen.borrow_mut().push(state.0))
+        };
+
+        batch(|| {
+            reduce_mut(|state: &mut TestState| state.0 += 1);
+            reduce_mut(|state: &mut TestState| state.0 -= 1);
+        });
+
+        assert!(seen.borrow().is_empty());
+    }
+
+    #[test]
+    fn reset_notifies_subscribers() {
+        let seen = Mrc::new(Vec::new());
+        let dispatch = {
+            let seen = seen.clone();
+            Dispatch::<TestState>::subscribe_silent(move |state: Rc<TestState>| {
+                seen.borrow_mut().push(state.0)
+            })
+        };
+
+        dispatch.set(TestState(1));
+        dispatch.reset();
+
+        assert_eq!(dispatch.get().0, 0);
+        assert_eq!(*seen.borrow(), vec![1, 0]);
+    }
+
+    #[test]
+    fn reset_all_keeps_subscriptions() {
+        let seen = Mrc::new(Vec::new());
+        let _id = {
+            let seen = seen.clone();
+            subscribe_silent(move |state: Rc<TestState>| seen.borrow_mut().push(state.0))
+        };
+
+        set(TestState(1));
+        set(TestState2(1));
+        reset_all();
+
+        assert_eq!(get::<TestState>().0, 0);
+        assert_eq!(get::<TestState2>().0, 0);
+        assert_eq!(*seen.borrow(), vec![1, 0]);
+
+        set(TestState(2));
+        assert_eq!(*seen.borrow(), vec![1, 0, 2]);
+    }
 }
@@ -0,0 +1,371 @@
+//! Side effects returned from reducers, such as fetching data, starting a timer or showing a toast.
+//!
+//! An [`EffectReducer`] returns an [`Effect`] along with the new state. Effects run after the
+//! state is committed and subscribers are notified (after the outermost batch, if in one), and are
+//! given a [`Dispatch`] so they can apply further changes. Changes vetoed by middleware, or rolled
+//! back by a transaction, don't run their effects.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use yewdux::{
+//!     effect::{Effect, EffectReducer},
+//!     prelude::*,
+//! };
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct User {
+//!     loading: bool,
+//!     name: Option<String>,
+//! }
+//!
+//! struct Fetch;
+//! impl EffectReducer<User> for Fetch {
+//!     fn apply(self, mut user: Rc<User>) -> (Rc<User>, Effect<User>) {
+//!         Rc::make_mut(&mut user).loading = true;
+//!
+//!         let effect = Effect::new("fetch user", |dispatch: Dispatch<User>| {
+//!             // Make the request here, e.g. in `spawn_local`.
+//!             dispatch.reduce_mut(|user| {
+//!                 user.loading = false;
+//!                 user.name = Some("Jane".into());
+//!             });
+//!         });
+//!
+//!         (user, effect)
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let dispatch = Dispatch::<User>::new();
+//! dispatch.apply_effect(Fetch);
+//!
+//! assert!(dispatch.get().name.as_deref() == Some("Jane"));
+//! # }
+//! ```
+//!
+//! Tests can intercept effects with [`Context::intercept_effects`], to check which effects were
+//! requested without running them.
+use std::{
+    any::type_name,
+    cell::RefCell,
+    fmt,
+    rc::{Rc, Weak},
+};
+
+use crate::{context::Context, dispatch::Dispatch, mrc::Mrc, store::Store};
+
+type Run<S> = Box<dyn FnOnce(Dispatch<S>)>;
+
+/// Side effects to run once a change is committed. Effects are named, so they can be told apart
+/// when intercepted.
+pub struct Effect<S: Store> {
+    effects: Vec<(&'static str, Run<S>)>,
+}
+
+impl<S: Store> Effect<S> {
+    /// No effect.
+    pub fn none() -> Self {
+        Self {
+            effects: Vec::new(),
+        }
+    }
+
+    /// An effect that runs `f`.
+    pub fn new(name: &'static str, f: impl FnOnce(Dispatch<S>) + 'static) -> Self {
+        Self {
+            effects: vec![(name, Box::new(f))],
+        }
+    }
+
+    /// Run `other` after this effect.
+    pub fn and(mut self, other: Self) -> Self {
+        self.effects.extend(other.effects);
+        self
+    }
+
+    /// Whether there are no effects to run.
+    pub fn is_none(&self) -> bool {
+        self.effects.is_empty()
+    }
+}
+
+impl<S: Store> Default for Effect<S> {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// A [`Reducer`](crate::store::Reducer) that also returns side effects to run.
+pub trait EffectReducer<S: Store> {
+    /// Mutate state, returning effects to run once the change is committed.
+    fn apply(self, state: Rc<S>) -> (Rc<S>, Effect<S>);
+}
+
+impl<F, S> EffectReducer<S> for F
+where
+    S: Store,
+    F: FnOnce(Rc<S>) -> (Rc<S>, Effect<S>),
+{
+    fn apply(self, state: Rc<S>) -> (Rc<S>, Effect<S>) {
+        self(state)
+    }
+}
+
+/// An effect that was intercepted instead of run.
+pub struct Intercepted {
+    /// Type name of the store whose reducer returned it.
+    pub store: &'static str,
+    /// Name given in [`Effect::new`].
+    pub name: &'static str,
+    run: Box<dyn FnOnce()>,
+}
+
+impl Intercepted {
+    /// Run the effect anyway.
+    pub fn run(self) {
+        (self.run)()
+    }
+}
+
+impl fmt::Debug for Intercepted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Intercepted")
+            .field("store", &self.store)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+type Queue = Rc<RefCell<Vec<Intercepted>>>;
+
+/// Collects effects instead of running them, for as long as it exists. See
+/// [`Context::intercept_effects`].
+pub struct Interceptor(Queue);
+
+impl Interceptor {
+    /// Take every effect intercepted so far.
+    pub fn take(&self) -> Vec<Intercepted> {
+        std::mem::take(&mut *self.0.borrow_mut())
+    }
+
+    /// Names of every effect intercepted so far, in order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.0.borrow().iter().map(|effect| effect.name).collect()
+    }
+}
+
+#[derive(Default)]
+struct Interception(Weak<RefCell<Vec<Intercepted>>>);
+impl Store for Mrc<Interception> {
+    fn new() -> Self {
+        Interception::default().into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+impl Context {
+    fn interception(&self) -> Mrc<Interception> {
+        let entry = self.get_or_init_internal::<Mrc<Interception>>();
+        let interception = entry.store.borrow().as_ref().clone();
+        interception
+    }
+
+    /// Collect effects returned from reducers in this context instead of running them, until the
+    /// returned [`Interceptor`] is dropped.
+    pub fn intercept_effects(&self) -> Interceptor {
+        let queue = Queue::default();
+        self.interception().borrow_mut().0 = Rc::downgrade(&queue);
+
+        Interceptor(queue)
+    }
+
+    /// Number of effects intercepted so far, see [`Self::discard_intercepted`].
+    pub(crate) fn intercepted_len(&self) -> usize {
+        let queue = self.interception().borrow().0.upgrade();
+        queue.map(|queue| queue.borrow().len()).unwrap_or_default()
+    }
+
+    /// Drop effects intercepted after the first `len`, such as those of a rolled back transaction.
+    pub(crate) fn discard_intercepted(&self, len: usize) {
+        if let Some(queue) = self.interception().borrow().0.upgrade() {
+            queue.borrow_mut().truncate(len);
+        }
+    }
+
+    /// Change state from a reducer that returns effects, running them once subscribers are
+    /// notified.
+    pub(crate) fn reduce_effect<S: Store, R: EffectReducer<S>>(&self, r: R) {
+        self.assert_writable::<S>();
+
+        let mut effect = Effect::none();
+        let committed = self.reduce_named(type_name::<R>(), |state| {
+            let (state, returned) = r.apply(state);
+            effect = returned;
+            state
+        });
+        if !committed {
+            return;
+        }
+
+        let queue = self.interception().borrow().0.upgrade();
+        for (name, run) in effect.effects {
+            let cx = self.clone();
+            let effect = Intercepted {
+                store: type_name::<S>(),
+                name,
+                run: Box::new(move || run(Dispatch::with_context(&cx))),
+            };
+
+            match &queue {
+                Some(queue) => queue.borrow_mut().push(effect),
+                None => self.after_notify(effect.run),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::{
+        middleware,
+        test_util::{isolated, TestState},
+    };
+
+    use super::*;
+
+    /// Sets the state, with an effect that logs and adds one.
+    fn set_then_add(value: u32, log: Rc<RefCell<Vec<String>>>) -> impl EffectReducer<TestState> {
+        move |_| {
+            let effect = Effect::new("add one", move |dispatch: Dispatch<TestState>| {
+                log.borrow_mut()
+                    .push(format!("effect {}", dispatch.get().0));
+                dispatch.reduce(|state| TestState(state.0 + 1).into());
+            });
+
+            (TestState(value).into(), effect)
+        }
+    }
+
+    fn setup() -> (Dispatch<TestState>, Rc<RefCell<Vec<String>>>) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let dispatch = {
+            let log = log.clone();
+            Dispatch::<TestState>::subscribe_silent_with_context(
+                &Context::new(),
+                move |state: Rc<TestState>| log.borrow_mut().push(format!("notified {}", state.0)),
+            )
+        };
+
+        (dispatch, log)
+    }
+
+    #[test]
+    fn effects_run_after_subscribers_are_notified() {
+        let (dispatch, log) = setup();
+
+        dispatch.apply_effect(set_then_add(1, log.clone()));
+
+        assert!(dispatch.get().0 == 2);
+        assert_eq!(*log.borrow(), vec!["notified 1", "effect 1", "notified 2"]);
+    }
+
+    #[test]
+    fn effects_run_after_batch() {
+        let (dispatch, log) = setup();
+
+        dispatch.batch(|dispatch| {
+            dispatch.apply_effect(set_then_add(1, log.clone()));
+            dispatch.set(TestState(5));
+            log.borrow_mut().push("batch done".into());
+        });
+
+        assert_eq!(
+            *log.borrow(),
+            vec!["batch done", "notified 5", "effect 5", "notified 6"]
+        );
+    }
+
+    #[test]
+    fn vetoed_changes_have_no_effects() {
+        #[derive(Clone, PartialEq, Eq)]
+        struct Vetoed;
+        impl Store for Vetoed {
+            fn new() -> Self {
+                Self
+            }
+
+            fn should_notify(&self, _: &Self) -> bool {
+                true
+            }
+        }
+
+        let dispatch = isolated::<Vetoed>();
+        middleware::register_with_context::<Vetoed, _>(dispatch.context(), |_| None);
+        let ran = Rc::new(Cell::new(false));
+
+        dispatch.apply_effect({
+            let ran = ran.clone();
+            move |state| (state, Effect::new("run", move |_| ran.set(true)))
+        });
+
+        assert!(!ran.get());
+    }
+
+    #[test]
+    fn rolled_back_changes_have_no_effects() {
+        let (dispatch, log) = setup();
+
+        let _ = dispatch.transaction(|tx| {
+            tx.apply_effect(set_then_add(1, log.clone()));
+            Err::<(), _>(())
+        });
+
+        assert!(dispatch.get().0 == 0);
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn rolled_back_changes_are_not_intercepted() {
+        let (dispatch, log) = setup();
+        let interceptor = dispatch.context().intercept_effects();
+
+        dispatch.apply_effect(set_then_add(1, log.clone()));
+        let _ = dispatch.transaction(|tx| {
+            tx.apply_effect(set_then_add(2, log.clone()));
+            Err::<(), _>(())
+        });
+
+        assert!(dispatch.get().0 == 1);
+        assert_eq!(interceptor.names(), vec!["add one"]);
+    }
+
+    #[test]
+    fn effects_can_be_intercepted() {
+        let (dispatch, log) = setup();
+        let interceptor = dispatch.context().intercept_effects();
+
+        dispatch.apply_effect(set_then_add(1, log.clone()));
+
+        assert_eq!(interceptor.names(), vec!["add one"]);
+        assert_eq!(*log.borrow(), vec!["notified 1"]);
+
+        let effects = interceptor.take();
+        assert!(effects[0].store == type_name::<TestState>());
+        for effect in effects {
+            effect.run();
+        }
+        assert!(dispatch.get().0 == 2);
+
+        // Effects run again once the interceptor is dropped.
+        drop(interceptor);
+        dispatch.apply_effect(set_then_add(1, log.clone()));
+        assert!(dispatch.get().0 == 2);
+        assert!(log.borrow().last().unwrap() == "notified 2");
+    }
+}
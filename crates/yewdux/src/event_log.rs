@@ -0,0 +1,422 @@
+//! Persist a store as a log of actions, instead of its full state.
+//!
+//! Every action applied through an [`EventLog`] is serialized and appended to the log. Loading
+//! replays the log onto the last snapshot of the store. After a number of actions the log is
+//! compacted, writing a new snapshot and clearing the actions.
+//!
+//! For large stores with small, frequent changes this writes far less than saving the whole store
+//! on every change (see [persistence](https://intendednull.github.io/yewdux/persistence.html)).
+//! Keys are prefixed with the type name of the store, and don't clash with the key used by
+//! `#[store(storage = ...)]`, so a store can use both.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use serde::{Deserialize, Serialize};
+//! use yewdux::{
+//!     event_log::{EventLog, Memory},
+//!     prelude::*,
+//! };
+//!
+//! #[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+//! struct Todos {
+//!     items: Vec<String>,
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! enum Action {
+//!     Add(String),
+//! }
+//!
+//! impl Reducer<Todos> for Action {
+//!     fn apply(self, mut todos: Rc<Todos>) -> Rc<Todos> {
+//!         match self {
+//!             Action::Add(item) => Rc::make_mut(&mut todos).items.push(item),
+//!         }
+//!
+//!         todos
+//!     }
+//! }
+//!
+//! thread_local! {
+//!     // Use `storage::Area::Local` in the browser.
+//!     static BACKEND: Memory = Memory::default();
+//! }
+//!
+//! fn log() -> EventLog<Todos, Action> {
+//!     EventLog::new(BACKEND.with(Clone::clone)).compact_after(100)
+//! }
+//!
+//! impl Store for Todos {
+//!     fn new() -> Self {
+//!         log().load().ok().flatten().unwrap_or_default()
+//!     }
+//!
+//!     fn should_notify(&self, other: &Self) -> bool {
+//!         self != other
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let dispatch = Dispatch::<Todos>::new();
+//! log().apply(&dispatch, Action::Add("Write docs".into())).unwrap();
+//!
+//! assert!(log().load().unwrap().unwrap().items.len() == 1);
+//! # }
+//! ```
+use std::{any::type_name, cell::RefCell, collections::HashMap, marker::PhantomData, rc::Rc};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    dispatch::Dispatch,
+    store::{Reducer, Store},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum EventLogError {
+    #[cfg(target_arch = "wasm32")]
+    #[error("A storage error occurred")]
+    Storage(#[from] crate::storage::StorageError),
+    #[error("A serde error occurred")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Where the log is kept, as string values by key.
+pub trait Backend: 'static {
+    fn get(&self, key: &str) -> Result<Option<String>, EventLogError>;
+
+    fn set(&self, key: &str, value: &str) -> Result<(), EventLogError>;
+
+    fn remove(&self, key: &str) -> Result<(), EventLogError>;
+}
+
+/// Keeps the log in memory. Useful for tests, and for rendering on the server.
+#[derive(Clone, Default)]
+pub struct Memory(Rc<RefCell<HashMap<String, String>>>);
+
+impl Backend for Memory {
+    fn get(&self, key: &str) -> Result<Option<String>, EventLogError> {
+        Ok(self.0.borrow().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), EventLogError> {
+        self.0
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), EventLogError> {
+        self.0.borrow_mut().remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Backend for crate::storage::Area {
+    fn get(&self, key: &str) -> Result<Option<String>, EventLogError> {
+        let storage = crate::storage::get_storage(*self)?;
+        let value = storage
+            .get(key)
+            .map_err(crate::storage::StorageError::WebSys)?;
+
+        Ok(value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), EventLogError> {
+        let storage = crate::storage::get_storage(*self)?;
+        storage
+            .set(key, value)
+            .map_err(crate::storage::StorageError::WebSys)?;
+
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), EventLogError> {
+        let storage = crate::storage::get_storage(*self)?;
+        storage
+            .remove_item(key)
+            .map_err(crate::storage::StorageError::WebSys)?;
+
+        Ok(())
+    }
+}
+
+/// A log of actions of type `M`, applied to `S`.
+///
+/// The log is kept entirely in the backend, so creating one is cheap. Changes made to `S` other
+/// than through [`EventLog::apply`] are not logged, call [`EventLog::compact`] to save them.
+pub struct EventLog<S, M> {
+    backend: Box<dyn Backend>,
+    compact_after: usize,
+    _marker: PhantomData<(S, M)>,
+}
+
+impl<S, M> EventLog<S, M>
+where
+    S: Store + Serialize + DeserializeOwned,
+    M: Reducer<S> + Serialize + DeserializeOwned,
+{
+    /// Create a log kept in the given backend. By default it is compacted after 100 actions.
+    pub fn new<B: Backend>(backend: B) -> Self {
+        Self {
+            backend: Box::new(backend),
+            compact_after: 100,
+            _marker: Default::default(),
+        }
+    }
+
+    /// Compact the log once it holds this many actions.
+    pub fn compact_after(mut self, actions: usize) -> Self {
+        self.compact_after = actions.max(1);
+        self
+    }
+
+    fn snapshot_key() -> String {
+        format!("{}.snapshot", type_name::<S>())
+    }
+
+    fn len_key() -> String {
+        format!("{}.events", type_name::<S>())
+    }
+
+    fn event_key(index: usize) -> String {
+        format!("{}.events.{}", type_name::<S>(), index)
+    }
+
+    /// Number of actions logged since the last compaction.
+    pub fn len(&self) -> Result<usize, EventLogError> {
+        match self.backend.get(&Self::len_key())? {
+            Some(len) => Ok(serde_json::from_str(&len)?),
+            None => Ok(0),
+        }
+    }
+
+    fn set_len(&self, len: usize) -> Result<(), EventLogError> {
+        self.backend
+            .set(&Self::len_key(), &serde_json::to_string(&len)?)
+    }
+
+    /// Whether no actions were logged since the last compaction.
+    pub fn is_empty(&self) -> Result<bool, EventLogError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Load the last snapshot, with every logged action applied to it. Returns `None` if nothing
+    /// was saved yet.
+    pub fn load(&self) -> Result<Option<S>, EventLogError>
+    where
+        S: Clone,
+    {
+        let snapshot = match self.backend.get(&Self::snapshot_key())? {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+
+        let mut state = Rc::new(serde_json::from_str::<S>(&snapshot)?);
+        for index in 0..self.len()? {
+            if let Some(event) = self.backend.get(&Self::event_key(index))? {
+                state = serde_json::from_str::<M>(&event)?.apply(state);
+            }
+        }
+
+        // Actions may keep a reference to the state, it's cloned if so.
+        Ok(Some(Rc::unwrap_or_clone(state)))
+    }
+
+    /// Apply an action, appending it to the log. Actions that don't change the state (including
+    /// those vetoed by middleware) are not logged.
+    pub fn apply(&self, dispatch: &Dispatch<S>, action: M) -> Result<(), EventLogError> {
+        let len = self.len()?;
+        let old = dispatch.get();
+        if len == 0 && self.backend.get(&Self::snapshot_key())?.is_none() {
+            // Actions are always applied to a snapshot.
+            self.backend
+                .set(&Self::snapshot_key(), &serde_json::to_string(old.as_ref())?)?;
+        }
+
+        // Take the next slot before applying, as subscribers may apply actions through this log
+        // while being notified. Those are logged after this one.
+        self.backend
+            .set(&Self::event_key(len), &serde_json::to_string(&action)?)?;
+        self.set_len(len + 1)?;
+
+        dispatch.apply(action);
+        if Rc::ptr_eq(&old, &dispatch.get()) {
+            // Nothing was notified, so nothing else was logged since.
+            self.backend.remove(&Self::event_key(len))?;
+            return self.set_len(len);
+        }
+
+        if self.len()? >= self.compact_after {
+            return self.compact(&dispatch.get());
+        }
+
+        Ok(())
+    }
+
+    /// Save a snapshot of `state`, and clear the logged actions.
+    pub fn compact(&self, state: &S) -> Result<(), EventLogError> {
+        self.backend
+            .set(&Self::snapshot_key(), &serde_json::to_string(state)?)?;
+        for index in 0..self.len()? {
+            self.backend.remove(&Self::event_key(index))?;
+        }
+        self.backend.remove(&Self::len_key())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::{
+        middleware,
+        test_util::{isolated, TestState},
+    };
+
+    use super::*;
+
+    thread_local! {
+        static KEPT: RefCell<Option<Rc<TestState>>> = Default::default();
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Msg {
+        Add(u32),
+        Double,
+        /// Adds one, keeping a reference to the new state.
+        Keep,
+    }
+
+    impl Reducer<TestState> for Msg {
+        fn apply(self, state: Rc<TestState>) -> Rc<TestState> {
+            match self {
+                Msg::Add(0) => state,
+                Msg::Add(n) => TestState(state.0 + n).into(),
+                Msg::Double => TestState(state.0 * 2).into(),
+                Msg::Keep => {
+                    let state = Rc::new(TestState(state.0 + 1));
+                    KEPT.with(|kept| *kept.borrow_mut() = Some(Rc::clone(&state)));
+                    state
+                }
+            }
+        }
+    }
+
+    fn setup(compact_after: usize) -> (Memory, EventLog<TestState, Msg>, Dispatch<TestState>) {
+        let backend = Memory::default();
+        let log = EventLog::new(backend.clone()).compact_after(compact_after);
+        let dispatch = isolated();
+
+        (backend, log, dispatch)
+    }
+
+    #[test]
+    fn load_is_none_when_empty() {
+        let (_, log, _) = setup(10);
+
+        assert!(log.load().unwrap().is_none());
+    }
+
+    #[test]
+    fn actions_are_appended_and_replayed() {
+        let (backend, log, dispatch) = setup(10);
+        dispatch.set(TestState(1));
+
+        log.apply(&dispatch, Msg::Add(2)).unwrap();
+        log.apply(&dispatch, Msg::Double).unwrap();
+
+        assert!(log.len().unwrap() == 2);
+        // The snapshot is written once, before the first action.
+        assert_eq!(
+            backend
+                .get(&EventLog::<TestState, Msg>::snapshot_key())
+                .unwrap()
+                .unwrap(),
+            "1"
+        );
+        assert!(log.load().unwrap() == Some(TestState(6)));
+        assert!(dispatch.get().0 == 6);
+    }
+
+    #[test]
+    fn log_is_compacted() {
+        let (backend, log, dispatch) = setup(3);
+
+        for _ in 0..4 {
+            log.apply(&dispatch, Msg::Add(1)).unwrap();
+        }
+
+        assert!(log.len().unwrap() == 1);
+        assert_eq!(
+            backend
+                .get(&EventLog::<TestState, Msg>::snapshot_key())
+                .unwrap()
+                .unwrap(),
+            "3"
+        );
+        assert!(backend.0.borrow().len() == 3);
+        assert!(log.load().unwrap() == Some(TestState(4)));
+    }
+
+    #[test]
+    fn unchanged_state_is_not_logged() {
+        let (_, log, dispatch) = setup(10);
+        middleware::register_with_context::<TestState, _>(
+            dispatch.context(),
+            |change: middleware::Change<TestState>| (change.new.0 < 100).then_some(change.new),
+        );
+
+        log.apply(&dispatch, Msg::Add(0)).unwrap();
+        log.apply(&dispatch, Msg::Add(100)).unwrap();
+
+        assert!(log.is_empty().unwrap());
+        assert!(log.load().unwrap() == Some(TestState(0)));
+    }
+
+    #[test]
+    fn actions_applied_by_subscribers_are_logged_in_order() {
+        let (backend, log, dispatch) = setup(10);
+        let _doubler = {
+            let cx = dispatch.context().clone();
+            let dispatch = dispatch.clone();
+            Dispatch::<TestState>::subscribe_silent_with_context(
+                &cx,
+                move |state: Rc<TestState>| {
+                    if state.0 == 1 {
+                        let log = EventLog::<TestState, Msg>::new(backend.clone());
+                        log.apply(&dispatch, Msg::Double).unwrap();
+                    }
+                },
+            )
+        };
+
+        log.apply(&dispatch, Msg::Add(1)).unwrap();
+
+        assert!(dispatch.get().0 == 2);
+        assert!(log.len().unwrap() == 2);
+        assert!(log.load().unwrap() == Some(TestState(2)));
+    }
+
+    #[test]
+    fn state_kept_by_actions_is_loaded() {
+        let (_, log, dispatch) = setup(10);
+
+        log.apply(&dispatch, Msg::Keep).unwrap();
+
+        assert!(log.load().unwrap() == Some(TestState(1)));
+    }
+
+    #[test]
+    fn snapshot_does_not_use_the_storage_key() {
+        let (backend, log, dispatch) = setup(10);
+
+        log.apply(&dispatch, Msg::Add(1)).unwrap();
+
+        assert!(backend.get(type_name::<TestState>()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,295 @@
+//! Track which fields of a store changed.
+//!
+//! Add `#[store(fields)]` to the `Store` macro to implement [`FieldChanges`], which compares each
+//! field to find which changed. Subscribers and selectors can then declare the fields they read,
+//! and are skipped when only other fields change.
+//!
+//! The macro also generates a struct named after the store with a `Fields` suffix, holding a
+//! [`FieldMask`] for each field, available as [`FieldChanges::FIELDS`]. Use
+//! `#[store(fields, fields_name = UserMasks)]` to give it another name, such as when the default
+//! one is already taken.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use yew::prelude::*;
+//! use yewdux::{
+//!     fields::{use_store_fields, FieldChanges},
+//!     prelude::*,
+//! };
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! #[store(fields)]
+//! struct User {
+//!     name: String,
+//!     email: String,
+//!     visits: u32,
+//! }
+//!
+//! #[function_component]
+//! fn Profile() -> Html {
+//!     // Doesn't re-render when only `visits` changes.
+//!     let (user, _) = use_store_fields::<User>(User::FIELDS.name | User::FIELDS.email);
+//!
+//!     html! {
+//!         <p>{ format!("{} <{}>", user.name, user.email) }</p>
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let old = User::default();
+//! let new = User {
+//!     visits: 1,
+//!     ..Default::default()
+//! };
+//!
+//! assert!(new.changed_fields(&old) == User::FIELDS.visits);
+//! # }
+//! ```
+use std::{
+    cell::RefCell,
+    ops::{BitAnd, BitOr, BitOrAssign, Deref},
+    rc::Rc,
+};
+
+use yew::functional::*;
+
+use crate::{
+    context::Context,
+    dispatch::Dispatch,
+    functional::{use_cx, use_selected},
+    store::Store,
+};
+
+/// A set of fields, one bit per field. Stores have up to 64 fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FieldMask(u64);
+
+impl FieldMask {
+    /// No fields.
+    pub const NONE: Self = Self(0);
+    /// Every field.
+    pub const ALL: Self = Self(u64::MAX);
+
+    /// The field at `index`, in order of declaration.
+    pub const fn field(index: u32) -> Self {
+        Self(1 << index)
+    }
+
+    /// Whether any field is in both masks.
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Whether every field in `other` is in this mask.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether there are no fields.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for FieldMask {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for FieldMask {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for FieldMask {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+/// A store that knows which of its fields changed. Use `#[store(fields)]` with the `Store` macro
+/// to implement it.
+pub trait FieldChanges: Store {
+    /// A [`FieldMask`] for each field.
+    type Fields: 'static;
+
+    /// Masks of every field, e.g. `User::FIELDS.name`.
+    const FIELDS: Self::Fields;
+
+    /// Fields that differ from `old`.
+    fn changed_fields(&self, old: &Self) -> FieldMask;
+}
+
+/// Wrap `on_change` so it is only called when any of `fields` changed.
+fn on_fields<S: FieldChanges>(
+    cx: &Context,
+    fields: FieldMask,
+    on_change: impl Fn(Rc<S>) + 'static,
+) -> impl Fn(Rc<S>) + 'static {
+    let last = RefCell::new(cx.get::<S>());
+    move |state: Rc<S>| {
+        let changed = state.changed_fields(&last.replace(Rc::clone(&state)));
+        if changed.intersects(fields) {
+            on_change(state);
+        }
+    }
+}
+
+impl<S: FieldChanges> Dispatch<S> {
+    /// Similar to [`Self::subscribe_silent`], but only called when any of `fields` changed.
+    pub fn subscribe_fields_silent<F: Fn(Rc<S>) + 'static>(
+        fields: FieldMask,
+        on_change: F,
+    ) -> Self {
+        Self::subscribe_fields_silent_with_context(&Context::global(), fields, on_change)
+    }
+
+    /// Similar to [`Self::subscribe_fields_silent`], but subscribes to the store in the given
+    /// context.
+    pub fn subscribe_fields_silent_with_context<F: Fn(Rc<S>) + 'static>(
+        cx: &Context,
+        fields: FieldMask,
+        on_change: F,
+    ) -> Self {
+        Self::subscribe_silent_with_context(cx, on_fields(cx, fields, on_change))
+    }
+}
+
+/// Similar to [`use_store`](crate::functional::use_store), but only re-renders when any of
+/// `fields` changed. See the [fields](crate::fields) module.
+#[hook]
+pub fn use_store_fields<S>(fields: FieldMask) -> (Rc<S>, Dispatch<S>)
+where
+    S: FieldChanges,
+{
+    let cx = use_cx();
+    let state = use_state(|| cx.get::<S>());
+
+    let dispatch = {
+        let state = state.clone();
+        use_memo(fields, move |fields| {
+            // Other fields may have changed while they weren't subscribed to.
+            let current = cx.get::<S>();
+            if current.changed_fields(&state).intersects(*fields) {
+                state.set(current);
+            }
+
+            Dispatch::<S>::subscribe_fields_silent_with_context(&cx, *fields, move |val| {
+                state.set(val)
+            })
+        })
+    };
+
+    (Rc::clone(&state), dispatch.deref().clone())
+}
+
+/// Similar to [`use_selector`](crate::functional::use_selector), but the selector only runs when
+/// any of `fields` changed. The selector must only read those fields.
+#[hook]
+pub fn use_selector_fields<S, F, R>(fields: FieldMask, selector: F) -> Rc<R>
+where
+    S: FieldChanges,
+    R: PartialEq + 'static,
+    F: Fn(&S) -> R + 'static,
+{
+    use_selected(
+        move |cx, _| Rc::new(selector(&cx.get::<S>())),
+        |a, b| a == b,
+        fields,
+        |cx, fields, on_change| {
+            Dispatch::<S>::subscribe_fields_silent_with_context(cx, *fields, move |_| on_change())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct User {
+        name: String,
+        visits: u32,
+    }
+    impl Store for User {
+        fn new() -> Self {
+            Self {
+                name: String::new(),
+                visits: 0,
+            }
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    struct UserFields {
+        name: FieldMask,
+        visits: FieldMask,
+    }
+
+    impl FieldChanges for User {
+        type Fields = UserFields;
+
+        const FIELDS: UserFields = UserFields {
+            name: FieldMask::field(0),
+            visits: FieldMask::field(1),
+        };
+
+        fn changed_fields(&self, old: &Self) -> FieldMask {
+            let mut changed = FieldMask::NONE;
+            if self.name != old.name {
+                changed |= Self::FIELDS.name;
+            }
+            if self.visits != old.visits {
+                changed |= Self::FIELDS.visits;
+            }
+            changed
+        }
+    }
+
+    #[test]
+    fn masks_combine() {
+        let both = User::FIELDS.name | User::FIELDS.visits;
+
+        assert!(both.contains(User::FIELDS.name));
+        assert!(both.intersects(User::FIELDS.visits));
+        assert!(!User::FIELDS.name.intersects(User::FIELDS.visits));
+        assert!((User::FIELDS.name & User::FIELDS.visits).is_empty());
+    }
+
+    #[test]
+    fn subscribers_are_skipped_when_other_fields_change() {
+        let calls = Rc::new(Cell::new(0));
+        let dispatch = {
+            let calls = calls.clone();
+            Dispatch::<User>::subscribe_fields_silent_with_context(
+                &Context::new(),
+                User::FIELDS.name,
+                move |_| calls.set(calls.get() + 1),
+            )
+        };
+
+        dispatch.reduce_mut(|user| user.visits += 1);
+        assert!(calls.get() == 0);
+
+        dispatch.reduce_mut(|user| user.name = "Jane".into());
+        assert!(calls.get() == 1);
+
+        dispatch.reduce_mut(|user| {
+            user.name = "John".into();
+            user.visits += 1;
+        });
+        assert!(calls.get() == 2);
+    }
+}
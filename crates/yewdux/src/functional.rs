@@ -2,12 +2,36 @@
 use std::{ops::Deref, rc::Rc};
 
 use yew::functional::*;
+#[cfg(feature = "future")]
+use yew::suspense::{Suspension, SuspensionResult};
 
+#[cfg(feature = "future")]
+use crate::async_store::{AsyncStore, LoadState, Loaded};
 use crate::{
-    dispatch::{self, Dispatch},
+    context::Context,
+    derived::{Select, SelectWithDeps, Sources},
+    dispatch::Dispatch,
     store::Store,
 };
 
+/// The [`Context`] provided by the nearest [`YewduxRoot`](crate::context_provider::YewduxRoot),
+/// or the global context if there is none.
+#[hook]
+pub(crate) fn use_cx() -> Context {
+    use_context::<Context>().unwrap_or_else(Context::global)
+}
+
+/// Provides a [`Dispatch`] for the current context, without subscribing to changes.
+#[hook]
+pub fn use_dispatch<S>() -> Dispatch<S>
+where
+    S: Store,
+{
+    let cx = use_cx();
+
+    Dispatch::with_context(&cx)
+}
+
 /// This hook allows accessing the state of a store. When the store is modified, a re-render is
 /// automatically triggered.
 ///
@@ -35,11 +59,14 @@ use crate::{
 /// ```
 #[hook]
 pub fn use_store<S: Store>() -> (Rc<S>, Dispatch<S>) {
-    let state = use_state(|| dispatch::get::<S>());
+    let cx = use_cx();
+    let state = use_state(|| cx.get::<S>());
 
     let dispatch = {
         let state = state.clone();
-        use_state(move || Dispatch::<S>::subscribe_silent(move |val| state.set(val)))
+        use_state(move || {
+            Dispatch::<S>::subscribe_silent_with_context(&cx, move |val| state.set(val))
+        })
     };
 
     (Rc::clone(&state), dispatch.deref().clone())
@@ -158,40 +185,194 @@ where
     F: Fn(&S, &D) -> R + 'static,
     E: Fn(&R, &R) -> bool + 'static,
 {
-    // Given to user, this is what we update to force a re-render.
-    let selected = {
-        let state = dispatch::get::<S>();
-        let value = selector(&state, &deps);
+    use_selected(
+        move |cx, deps| Rc::new(selector(&cx.get::<S>(), deps)),
+        eq,
+        deps,
+        |cx, _, on_change| Dispatch::<S>::subscribe_silent_with_context(cx, move |_| on_change()),
+    )
+}
 
-        use_state(|| Rc::new(value))
-    };
+/// Like [`use_selector`], but selects from a tuple of stores. Subscribes to every store, only
+/// re-rendering when the selected value changes.
+///
+/// # Example
+/// ```
+/// use yew::prelude::*;
+/// use yewdux::prelude::*;
+///
+/// #[derive(Default, Clone, PartialEq, Store)]
+/// struct User {
+///     editor: bool,
+/// }
+///
+/// #[derive(Default, Clone, PartialEq, Store)]
+/// struct Document {
+///     locked: bool,
+/// }
+///
+/// #[function_component]
+/// fn EditButton() -> Html {
+///     let can_edit = use_multi_selector::<(User, Document), _, _>(
+///         |user: &User, document: &Document| user.editor && !document.locked,
+///     );
+///
+///     html! {
+///         <button disabled={!*can_edit}>{ "Edit" }</button>
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_multi_selector<S, F, R>(selector: F) -> Rc<R>
+where
+    S: Sources,
+    R: PartialEq + 'static,
+    F: Select<S, R> + 'static,
+{
+    use_multi_selector_eq::<S, F, R, _>(selector, |a, b| a == b)
+}
+
+/// Similar to [`use_multi_selector`], with a custom equality function, similar to
+/// [`use_selector_eq`].
+#[hook]
+pub fn use_multi_selector_eq<S, F, R, E>(selector: F, eq: E) -> Rc<R>
+where
+    S: Sources,
+    R: 'static,
+    F: Select<S, R> + 'static,
+    E: Fn(&R, &R) -> bool + 'static,
+{
+    use_sources_selector::<S, _, R, _, E>(move |states, _: &()| selector.select(states), eq, ())
+}
+
+/// Similar to [`use_multi_selector`], with additional dependencies, similar to
+/// [`use_selector_with_deps`]. The selector is given the dependencies last, e.g.
+/// `Fn(&A, &B, &D) -> R`.
+#[hook]
+pub fn use_multi_selector_with_deps<S, F, R, D>(selector: F, deps: D) -> Rc<R>
+where
+    S: Sources,
+    R: PartialEq + 'static,
+    D: Clone + PartialEq + 'static,
+    F: SelectWithDeps<S, D, R> + 'static,
+{
+    use_multi_selector_eq_with_deps::<S, F, R, D, _>(selector, |a, b| a == b, deps)
+}
+
+/// Similar to [`use_multi_selector_with_deps`], but also allows an equality function, similar to
+/// [`use_selector_eq`].
+#[hook]
+pub fn use_multi_selector_eq_with_deps<S, F, R, D, E>(selector: F, eq: E, deps: D) -> Rc<R>
+where
+    S: Sources,
+    R: 'static,
+    D: Clone + PartialEq + 'static,
+    F: SelectWithDeps<S, D, R> + 'static,
+    E: Fn(&R, &R) -> bool + 'static,
+{
+    use_sources_selector::<S, _, R, D, E>(
+        move |states, deps| selector.select(states, deps),
+        eq,
+        deps,
+    )
+}
+
+#[hook]
+fn use_sources_selector<S, F, R, D, E>(selector: F, eq: E, deps: D) -> Rc<R>
+where
+    S: Sources,
+    R: 'static,
+    D: Clone + PartialEq + 'static,
+    F: Fn(&S::States, &D) -> R + 'static,
+    E: Fn(&R, &R) -> bool + 'static,
+{
+    use_selected(
+        // Every store is read again, so changing several in a batch re-renders once.
+        move |cx, deps| Rc::new(selector(&S::get(cx), deps)),
+        eq,
+        deps,
+        |cx, _, on_change| S::subscribe(cx, move || on_change()),
+    )
+}
+
+/// Selects a value from the context with `select`, and selects it again whenever the subscription
+/// made by `subscribe` calls back, re-rendering only if `eq` says the value changed. The
+/// subscription is made again when `deps` change.
+#[hook]
+pub(crate) fn use_selected<R, D, F, E, N, T>(select: F, eq: E, deps: D, subscribe: N) -> Rc<R>
+where
+    R: 'static,
+    D: Clone + PartialEq + 'static,
+    F: Fn(&Context, &D) -> Rc<R> + 'static,
+    E: Fn(&R, &R) -> bool + 'static,
+    N: FnOnce(&Context, &D, Rc<dyn Fn()>) -> T,
+    T: 'static,
+{
+    let cx = use_cx();
+    // Given to user, this is what we update to force a re-render.
+    let selected = use_state(|| select(&cx, &deps));
     // Local tracking value, because `selected` isn't updated in our subscriber scope.
     let current = {
         let value = Rc::clone(&selected);
         use_mut_ref(|| value)
     };
+    // Whether the subscription wasn't made yet, in which case `selected` is up to date.
+    let first = use_mut_ref(|| true);
 
-    let _dispatch = {
+    let _subscription = {
         let selected = selected.clone();
-        use_memo(
-            deps,
-            move |deps| {
+        use_memo(deps, move |deps| {
+            let on_change: Rc<dyn Fn()> = {
                 let deps = deps.clone();
-                Dispatch::subscribe(move |val: Rc<S>| {
-                    let value = selector(&val, &deps);
+                let weak = cx.downgrade();
+                Rc::new(move || {
+                    let Some(cx) = weak.upgrade() else {
+                        return;
+                    };
+                    let value = select(&cx, &deps);
 
-                    if !eq(&current.borrow(), &value) {
-                        let value = Rc::new(value);
-                        // Update value for user.
+                    let changed = {
+                        let current = current.borrow();
+                        !Rc::ptr_eq(&current, &value) && !eq(&current, &value)
+                    };
+                    if changed {
                         selected.set(Rc::clone(&value));
-                        // Make sure to update our tracking value too.
-                        *current.borrow_mut() = Rc::clone(&value);
+                        *current.borrow_mut() = value;
                     }
                 })
-            },
+            };
+            // Subscriptions are silent, so select with the new deps now.
+            if !first.replace(false) {
+                on_change();
+            }
 
-        )
+            subscribe(&cx, deps, on_change)
+        })
     };
 
     Rc::clone(&selected)
 }
+
+/// Like [`use_store`], but suspends until an [`AsyncStore`] is loaded. Returns the error if
+/// loading failed. See the [async_store](crate::async_store) module.
+///
+/// [`AsyncStore`]: crate::async_store::AsyncStore
+#[cfg(feature = "future")]
+#[hook]
+pub fn use_store_suspense<S>() -> SuspensionResult<Loaded<S>>
+where
+    S: AsyncStore,
+{
+    let (state, dispatch) = use_store::<S>();
+
+    match dispatch.load_state() {
+        LoadState::Ready => Ok(Ok((state, dispatch))),
+        LoadState::Failed(err) => Ok(Err(err)),
+        LoadState::Idle | LoadState::Loading => {
+            let load = dispatch.load();
+            Err(Suspension::from_future(async move {
+                let _ = load.await;
+            }))
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Transfer store state from a server side render to the client.
+//!
+//! Stores opt in with the `hydrate` attribute. On the server, their state is serialized into a
+//! `<script>` element along with the rendered page. On the client, `Store::new` reads it back
+//! before falling back to storage or `Default`.
+//!
+//! State is keyed by the [name](crate::store::Named) of each store, as the server and client are
+//! different builds, often for different targets.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use yew::prelude::*;
+//! use yewdux::{hydration::HydrationScript, prelude::*};
+//!
+//! #[derive(Default, Clone, PartialEq, Serialize, Deserialize, Store)]
+//! #[store(hydrate)]
+//! struct Session {
+//!     user: String,
+//! }
+//!
+//! #[function_component]
+//! fn App() -> Html {
+//!     html! {
+//!         <YewduxRoot>
+//!             // ...
+//!             // Render this last, so it sees every store initialized during the render.
+//!             <HydrationScript />
+//!         </YewduxRoot>
+//!     }
+//! }
+//! ```
+use std::cell::RefCell;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use yew::prelude::*;
+
+use crate::{
+    context::Context,
+    functional::use_cx,
+    registry::register_serde,
+    store::{Named, Store},
+};
+
+/// Id of the `<script>` element holding the serialized state.
+pub const SCRIPT_ID: &str = "__yewdux_state";
+
+thread_local! {
+    /// State received from the server, keyed by store name.
+    static PAYLOAD: RefCell<Option<Map<String, Value>>> = Default::default();
+}
+
+/// Mark a store as hydratable, so it is included in [`to_json`]. This is called by the `Store`
+/// macro when using `#[store(hydrate)]`.
+pub fn register<S: Store + Serialize + Named>() {
+    register_serde::<S>(|serde| serde.hydrate = true);
+}
+
+/// Serialize every initialized, hydratable store in the given context.
+pub fn to_json(cx: &Context) -> Value {
+    let mut map = Map::new();
+    for serde in crate::registry::serde_all() {
+        if !serde.hydrate {
+            continue;
+        }
+
+        match (serde.serialize)(cx) {
+            Some(Ok(value)) => {
+                map.insert(serde.name.to_string(), value);
+            }
+            Some(Err(err)) => {
+                crate::log::error!(
+                    "Unable to serialize {} for hydration: {:?}",
+                    serde.type_name,
+                    err
+                );
+            }
+            None => {}
+        }
+    }
+
+    Value::Object(map)
+}
+
+/// Serialize every initialized, hydratable store in the given context, as a `<script>` element
+/// ready to be embedded in the page.
+pub fn script(cx: &Context) -> String {
+    // `<` can only appear inside JSON strings, where it may be escaped. This makes sure the
+    // payload can never close the script element early.
+    let json = to_json(cx).to_string().replace('<', "\\u003c");
+
+    format!(r#"<script type="application/json" id="{SCRIPT_ID}">{json}</script>"#)
+}
+
+/// Renders the [`script`] for the current context.
+#[function_component]
+pub fn HydrationScript() -> Html {
+    let cx = use_cx();
+
+    Html::from_html_unchecked(script(&cx).into())
+}
+
+/// Provide the state received from the server. On wasm this is read from the page automatically,
+/// so it is only needed when the payload is delivered some other way.
+pub fn set_payload(json: &str) -> Result<(), serde_json::Error> {
+    let map = serde_json::from_str(json)?;
+
+    PAYLOAD
+        .try_with(|payload| *payload.borrow_mut() = Some(map))
+        .expect("PAYLOAD thread local key init failed");
+
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_script() -> Option<Map<String, Value>> {
+    let json = web_sys::window()?
+        .document()?
+        .get_element_by_id(SCRIPT_ID)?
+        .text_content()?;
+
+    match serde_json::from_str(&json) {
+        Ok(map) => Some(map),
+        Err(err) => {
+            crate::log::error!("Unable to parse hydration state: {:?}", err);
+            None
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_script() -> Option<Map<String, Value>> {
+    None
+}
+
+/// Take the state of `S` received from the server. Each state can only be taken once, so later
+/// calls (e.g. for a new context) fall back to their usual initialization.
+pub fn load<S: DeserializeOwned + Named>() -> Result<Option<S>, serde_json::Error> {
+    let value = PAYLOAD
+        .try_with(|payload| {
+            payload
+                .borrow_mut()
+                .get_or_insert_with(|| read_script().unwrap_or_default())
+                .remove(S::NAME)
+        })
+        .expect("PAYLOAD thread local key init failed");
+
+    value.map(serde_json::from_value).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+    struct TestState(String);
+    impl Named for TestState {
+        const NAME: &'static str = "test";
+    }
+    impl Store for TestState {
+        fn new() -> Self {
+            register::<Self>();
+            Self("new".into())
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct NotHydrated(u32);
+    impl Store for NotHydrated {
+        fn new() -> Self {
+            Self(0)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    #[test]
+    fn only_initialized_stores_are_serialized() {
+        let cx = Context::new();
+        register::<TestState>();
+
+        assert_eq!(to_json(&cx), Value::Object(Map::new()));
+
+        cx.set(TestState("server".into()));
+        cx.set(NotHydrated(1));
+
+        assert_eq!(
+            to_json(&cx),
+            serde_json::json!({ "test": "server" })
+        );
+    }
+
+    #[test]
+    fn script_cannot_be_closed_early() {
+        let cx = Context::new();
+        cx.set(TestState("</script>".into()));
+
+        let script = script(&cx);
+
+        assert!(script.starts_with(r#"<script type="application/json" id="__yewdux_state">"#));
+        assert_eq!(script.matches("</script>").count(), 1);
+    }
+
+    #[test]
+    fn load_takes_state_from_payload() {
+        let server = Context::new();
+        server.set(TestState("server".into()));
+        set_payload(&to_json(&server).to_string()).unwrap();
+
+        assert_eq!(load::<TestState>().unwrap(), Some(TestState("server".into())));
+        assert_eq!(load::<TestState>().unwrap(), None);
+    }
+
+    #[test]
+    fn load_without_payload_is_none() {
+        assert_eq!(load::<TestState>().unwrap(), None);
+        assert!(Context::new().get::<TestState>() == Rc::new(TestState("new".into())));
+    }
+}
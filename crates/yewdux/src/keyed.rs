@@ -0,0 +1,380 @@
+//! Many instances of the same store, one per key.
+//!
+//! ```
+//! use yewdux::{keyed::KeyedStore, prelude::*};
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Document {
+//!     id: u32,
+//!     text: String,
+//! }
+//!
+//! impl KeyedStore for Document {
+//!     type Key = u32;
+//!
+//!     fn new(id: &u32) -> Self {
+//!         Self {
+//!             id: *id,
+//!             ..Default::default()
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let first = Dispatch::<Document>::keyed(1);
+//! first.reduce_mut(|doc| doc.text = "Hello".into());
+//!
+//! let second = Dispatch::<Document>::keyed(2);
+//! assert!(second.get().id == 2);
+//! assert!(second.get().text.is_empty());
+//! # }
+//! ```
+use std::{collections::HashMap, hash::Hash, rc::Rc};
+
+use yew::functional::*;
+
+use crate::{context::Context, dispatch::Dispatch, functional::use_cx, mrc::Mrc, store::Store};
+
+/// A store with one instance per key.
+///
+/// The unkeyed instance, accessed through [`Dispatch::new`], is still created with
+/// [`Store::new`].
+pub trait KeyedStore: Store {
+    type Key: Clone + Eq + Hash + 'static;
+
+    /// Create the instance for the given key.
+    fn new(key: &Self::Key) -> Self;
+}
+
+/// Every instance of `S`, each living in its own context.
+struct Instances<S: KeyedStore>(HashMap<S::Key, Context>);
+impl<S: KeyedStore> Store for Mrc<Instances<S>> {
+    fn new() -> Self {
+        Instances(HashMap::new()).into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+impl Context {
+    fn instances<S: KeyedStore>(&self) -> Mrc<Instances<S>> {
+        self.register_internal::<Mrc<Instances<S>>>(reset_instances::<S>);
+        let entry = self.get_or_init_internal::<Mrc<Instances<S>>>();
+        let instances = entry.store.borrow().as_ref().clone();
+        instances
+    }
+
+    /// The context holding the instance of `S` for `key`, creating it if needed.
+    pub(crate) fn keyed<S: KeyedStore>(&self, key: &S::Key) -> Context {
+        let instances = self.instances::<S>();
+        if let Some(cx) = instances.borrow().0.get(key) {
+            // An instance dropped when unused leaves its context behind, see `Store::retain`. It
+            // is created again from its key below.
+            if cx.try_get::<S>().is_some() {
+                return cx.clone();
+            }
+        }
+
+        // Create outside of borrow, so the new instance can access other instances.
+        let value = <S as KeyedStore>::new(key);
+
+        let mut instances = instances.borrow_mut();
+        let cx = instances
+            .0
+            .entry(key.clone())
+            .or_insert_with(|| self.child());
+        cx.init(value);
+
+        cx.clone()
+    }
+
+    /// Every instance of `S` that currently exists, skipping those dropped when unused.
+    fn live_instances<S: KeyedStore>(&self) -> Vec<(S::Key, Context)> {
+        self.instances::<S>()
+            .borrow()
+            .0
+            .iter()
+            .filter(|(_, cx)| cx.try_get::<S>().is_some())
+            .map(|(key, cx)| (key.clone(), cx.clone()))
+            .collect()
+    }
+
+    pub(crate) fn keys<S: KeyedStore>(&self) -> Vec<S::Key> {
+        self.live_instances::<S>()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    pub(crate) fn remove_key<S: KeyedStore>(&self, key: &S::Key) -> bool {
+        let instances = self.instances::<S>();
+        let mut instances = instances.borrow_mut();
+        // Removing an instance in use would leave its users with a different instance than
+        // everyone after.
+        match instances.0.get(key) {
+            Some(cx) if !cx.in_use::<S>() => {}
+            _ => return false,
+        }
+
+        instances.0.remove(key).is_some()
+    }
+}
+
+/// Reset every instance to its initial value for its key. Instances are kept, so subscriptions
+/// to them stay attached.
+fn reset_instances<S: KeyedStore>(cx: &Context) {
+    for (key, cx) in cx.live_instances::<S>() {
+        let value = <S as KeyedStore>::new(&key);
+        cx.reduce_unchecked(move |_| Rc::new(value));
+    }
+}
+
+impl<S: KeyedStore> Dispatch<S> {
+    /// Create a dispatch for the instance of `S` with the given key, in the global context.
+    pub fn keyed(key: S::Key) -> Self {
+        Self::keyed_with_context(&Context::global(), key)
+    }
+
+    /// Create a dispatch for the instance of `S` with the given key, in the given context.
+    pub fn keyed_with_context(cx: &Context, key: S::Key) -> Self {
+        Self::with_context(&cx.keyed::<S>(&key))
+    }
+
+    /// Keys of every instance of `S` in the global context.
+    pub fn keys() -> Vec<S::Key> {
+        Context::global().keys::<S>()
+    }
+
+    /// Keys of every instance of `S` in the given context.
+    pub fn keys_with_context(cx: &Context) -> Vec<S::Key> {
+        cx.keys::<S>()
+    }
+
+    /// Remove the instance of `S` with the given key from the global context, returning whether
+    /// it was removed. Instances still in use, by a dispatch or subscriber, are not removed. The
+    /// next access to the key gets a fresh instance.
+    pub fn remove_key(key: &S::Key) -> bool {
+        Context::global().remove_key::<S>(key)
+    }
+
+    /// Remove the instance of `S` with the given key from the given context, returning whether it
+    /// was removed. See [`Self::remove_key`].
+    pub fn remove_key_with_context(cx: &Context, key: &S::Key) -> bool {
+        cx.remove_key::<S>(key)
+    }
+}
+
+/// Similar to [`use_store`](crate::functional::use_store), for the instance of `S` with the
+/// given key.
+///
+/// # Example
+/// ```
+/// # use yew::prelude::*;
+/// # use yewdux::{keyed::{use_store_keyed, KeyedStore}, prelude::*};
+/// # #[derive(Default, Clone, PartialEq, Store)]
+/// # struct Room {
+/// #     messages: Vec<String>,
+/// # }
+/// # impl KeyedStore for Room {
+/// #     type Key = String;
+/// #     fn new(_: &String) -> Self {
+/// #         Default::default()
+/// #     }
+/// # }
+/// #[derive(Properties, PartialEq)]
+/// struct Props {
+///     room: String,
+/// }
+///
+/// #[function_component]
+/// fn ChatRoom(props: &Props) -> Html {
+///     let (room, dispatch) = use_store_keyed::<Room>(props.room.clone());
+///     let onclick = dispatch.reduce_mut_callback(|room| room.messages.push("Hi!".into()));
+///
+///     html! {
+///         <>
+///         <p>{ room.messages.len() }</p>
+///         <button {onclick}>{"Say hi"}</button>
+///         </>
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_store_keyed<S>(key: S::Key) -> (Rc<S>, Dispatch<S>)
+where
+    S: KeyedStore,
+{
+    let cx = use_cx();
+    let update = use_force_update();
+    let dispatch = use_memo(key, move |key| {
+        Dispatch::subscribe_silent_with_context(&cx.keyed::<S>(key), move |_| update.force_update())
+    });
+
+    (dispatch.get(), dispatch.as_ref().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Doc {
+        id: u32,
+        edits: u32,
+    }
+    impl Store for Doc {
+        fn new() -> Self {
+            Self { id: 0, edits: 0 }
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+    impl KeyedStore for Doc {
+        type Key = u32;
+
+        fn new(key: &u32) -> Self {
+            Self { id: *key, edits: 0 }
+        }
+    }
+
+    #[test]
+    fn instances_are_created_from_key() {
+        assert!(Dispatch::<Doc>::keyed(1).get().id == 1);
+        assert!(Dispatch::<Doc>::keyed(2).get().id == 2);
+        assert!(Dispatch::<Doc>::new().get().id == 0);
+    }
+
+    #[test]
+    fn instances_are_independent() {
+        Dispatch::<Doc>::keyed(1).reduce_mut(|doc| doc.edits += 1);
+
+        assert!(Dispatch::<Doc>::keyed(1).get().edits == 1);
+        assert!(Dispatch::<Doc>::keyed(2).get().edits == 0);
+    }
+
+    #[test]
+    fn subscribers_are_per_key() {
+        let calls = Rc::new(Cell::new(0));
+        let _dispatch = {
+            let calls = calls.clone();
+            Dispatch::<Doc>::subscribe_silent_with_context(
+                Dispatch::<Doc>::keyed(1).context(),
+                move |_| calls.set(calls.get() + 1),
+            )
+        };
+
+        Dispatch::<Doc>::keyed(2).reduce_mut(|doc| doc.edits += 1);
+        assert!(calls.get() == 0);
+
+        Dispatch::<Doc>::keyed(1).reduce_mut(|doc| doc.edits += 1);
+        assert!(calls.get() == 1);
+    }
+
+    #[test]
+    fn keys_can_be_listed_and_removed() {
+        Dispatch::<Doc>::keyed(1).reduce_mut(|doc| doc.edits += 1);
+        Dispatch::<Doc>::keyed(2);
+
+        let mut keys = Dispatch::<Doc>::keys();
+        keys.sort();
+        assert_eq!(keys, vec![1, 2]);
+
+        assert!(Dispatch::<Doc>::remove_key(&1));
+        assert!(!Dispatch::<Doc>::remove_key(&1));
+        assert_eq!(Dispatch::<Doc>::keys(), vec![2]);
+
+        // A removed key starts over.
+        assert!(Dispatch::<Doc>::keyed(1).get().edits == 0);
+    }
+
+    #[test]
+    fn keys_in_use_are_not_removed() {
+        let cx = Context::new();
+        let dispatch = Dispatch::<Doc>::keyed_with_context(&cx, 1);
+        dispatch.reduce_mut(|doc| doc.edits += 1);
+
+        assert!(!Dispatch::<Doc>::remove_key_with_context(&cx, &1));
+        // Still the same instance.
+        assert!(Dispatch::<Doc>::keyed_with_context(&cx, 1).get().edits == 1);
+
+        drop(dispatch);
+        assert!(Dispatch::<Doc>::remove_key_with_context(&cx, &1));
+    }
+
+    #[test]
+    fn dropped_instances_are_created_from_key() {
+        #[derive(Clone, PartialEq, Eq)]
+        struct Draft(u32);
+        impl Store for Draft {
+            fn new() -> Self {
+                Self(0)
+            }
+
+            fn should_notify(&self, other: &Self) -> bool {
+                self != other
+            }
+
+            fn retain(&self) -> bool {
+                false
+            }
+        }
+        impl KeyedStore for Draft {
+            type Key = u32;
+
+            fn new(key: &u32) -> Self {
+                Self(*key)
+            }
+        }
+
+        let cx = Context::new();
+        let dispatch = Dispatch::<Draft>::keyed_with_context(&cx, 1);
+        dispatch.reduce_mut(|draft| draft.0 += 10);
+        drop(dispatch);
+
+        assert!(Dispatch::<Draft>::keys_with_context(&cx).is_empty());
+        assert!(Dispatch::<Draft>::keyed_with_context(&cx, 1).get().0 == 1);
+    }
+
+    #[test]
+    fn keyed_instances_are_batched_with_parent() {
+        let calls = Rc::new(Cell::new(0));
+        let _dispatch = {
+            let calls = calls.clone();
+            Dispatch::<Doc>::subscribe_silent_with_context(
+                Dispatch::<Doc>::keyed(1).context(),
+                move |_| calls.set(calls.get() + 1),
+            )
+        };
+
+        crate::dispatch::batch(|| {
+            Dispatch::<Doc>::keyed(1).reduce_mut(|doc| doc.edits += 1);
+            Dispatch::<Doc>::keyed(1).reduce_mut(|doc| doc.edits += 1);
+        });
+
+        assert!(calls.get() == 1);
+    }
+
+    #[test]
+    fn reset_all_resets_keyed_instances() {
+        let calls = Rc::new(Cell::new(0));
+        let _dispatch = {
+            let calls = calls.clone();
+            Dispatch::<Doc>::subscribe_silent_with_context(
+                Dispatch::<Doc>::keyed(1).context(),
+                move |_| calls.set(calls.get() + 1),
+            )
+        };
+        Dispatch::<Doc>::keyed(1).reduce_mut(|doc| doc.edits += 1);
+
+        crate::reset_all();
+
+        assert!(Dispatch::<Doc>::keyed(1).get().edits == 0);
+        assert!(calls.get() == 2);
+    }
+}
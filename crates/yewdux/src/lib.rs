@@ -30,15 +30,41 @@
 //! ```
 #![allow(clippy::needless_doctest_main)]
 
-mod context;
+#[cfg(feature = "future")]
+pub mod async_store;
+pub mod changes;
+#[cfg(feature = "future")]
+pub mod conflict;
+pub mod context;
+pub mod context_provider;
+pub mod derived;
+#[cfg(feature = "devtools")]
+pub mod devtools;
 pub mod dispatch;
+pub mod effect;
+pub mod event_log;
+pub mod fields;
 pub mod functional;
+pub mod hydration;
+pub mod keyed;
 pub mod listener;
+pub mod middleware;
 pub mod mrc;
+pub mod recorder;
+pub mod registry;
+pub mod selector;
 #[cfg(target_arch = "wasm32")]
 pub mod storage;
 pub mod store;
 mod subscriber;
+#[cfg(feature = "future")]
+pub mod task;
+#[cfg(test)]
+mod test_util;
+pub mod transaction;
+
+pub use context::Context;
+pub use dispatch::reset_all;
 
 // Used by macro.
 #[doc(hidden)]
@@ -52,17 +78,20 @@ pub mod prelude {
     //! Default exports
 
     pub use crate::{
+        context_provider::YewduxRoot,
         dispatch::Dispatch,
         functional::{
-            use_selector, use_selector_eq, use_selector_eq_with_deps, use_selector_with_deps,
-            use_store, use_store_value,
+            use_dispatch, use_multi_selector, use_multi_selector_eq,
+            use_multi_selector_eq_with_deps, use_multi_selector_with_deps, use_selector,
+            use_selector_eq, use_selector_eq_with_deps, use_selector_with_deps, use_store,
+            use_store_value,
         },
-        listener::{init_listener, Listener},
+        listener::{init_listener, init_listener_with_context, Listener},
         store::{Reducer, Store},
     };
 
     #[cfg(feature = "future")]
-    pub use crate::store::AsyncReducer;
+    pub use crate::{async_store::AsyncStore, functional::use_store_suspense, store::AsyncReducer};
     #[cfg(feature = "future")]
     pub use yewdux_macros::async_reducer;
 }
@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use crate::{dispatch, mrc::Mrc, store::Store, subscriber::SubscriberId};
+use crate::{context::Context, mrc::Mrc, store::Store, subscriber::SubscriberId};
 
 /// Listens to [Store](crate::store::Store) changes.
 pub trait Listener: 'static {
@@ -20,15 +20,26 @@ impl<L: Listener> Store for Mrc<ListenerStore<L>> {
     }
 }
 
-/// Initiate a [Listener]. If this listener has already been initiated, it is dropped and replaced
-/// with the new one.
+/// Initiate a [Listener] in the global context. If this listener has already been initiated, it is
+/// dropped and replaced with the new one.
 pub fn init_listener<L: Listener>(listener: L) {
+    init_listener_with_context(&Context::global(), listener)
+}
+
+/// Similar to [init_listener], but listens to the store in the given context. Stores should use
+/// this in [`Store::new_with_context`], so their listeners follow them into other contexts, such
+/// as those of [`YewduxRoot`](crate::context_provider::YewduxRoot).
+pub fn init_listener_with_context<L: Listener>(cx: &Context, listener: L) {
     let id = {
         let listener = Mrc::new(listener);
-        dispatch::subscribe_silent(move |state| listener.borrow_mut().on_change(state))
+        cx.subscribe_silent(move |state| listener.borrow_mut().on_change(state))
     };
 
-    dispatch::reduce_mut(|state: &mut Mrc<ListenerStore<L>>| state.borrow_mut().0 = Some(id));
+    cx.get_or_init_internal::<Mrc<ListenerStore<L>>>()
+        .store
+        .borrow()
+        .borrow_mut()
+        .0 = Some(id);
 }
 
 #[cfg(test)]
@@ -36,6 +47,8 @@ mod tests {
 
     use std::cell::Cell;
 
+    use crate::dispatch::{self, Dispatch};
+
     use super::*;
 
     #[derive(Clone, PartialEq, Eq)]
@@ -144,4 +157,23 @@ mod tests {
     fn can_init_listener_from_store() {
         dispatch::get::<TestState2>();
     }
+
+    #[test]
+    fn listeners_are_per_context() {
+        let global = TestListener(Default::default());
+        let scoped = TestListener(Default::default());
+        let cx = Context::new();
+
+        init_listener(global.clone());
+        init_listener_with_context(&cx, scoped.clone());
+
+        Dispatch::<TestState>::with_context(&cx).set(TestState(1));
+        assert_eq!(global.0.get(), 0);
+        assert_eq!(scoped.0.get(), 1);
+
+        // Still listening, and not replaced by the scoped one.
+        dispatch::reduce_mut(|state: &mut TestState| state.0 = 2);
+        assert_eq!(global.0.get(), 2);
+        assert_eq!(scoped.0.get(), 1);
+    }
 }
@@ -0,0 +1,341 @@
+//! Run code around every change, such as logging, validation or metrics.
+//!
+//! Middleware sees every change a reducer makes, before it is applied. It may pass the new state
+//! through, replace it, or veto the change entirely. Global middleware sees changes to every store,
+//! while store middleware only sees changes to its store.
+//!
+//! Middleware is registered in a [`Context`], and only sees changes made there. [`register`] and
+//! [`register_global`] use the global context, while [`register_with_context`] and
+//! [`register_global_with_context`] take one, such as that of a
+//! [`YewduxRoot`](crate::context_provider::YewduxRoot). Middleware from the `middleware` attribute
+//! of the `Store` macro is registered in every context the store is created in.
+//!
+//! For every change, global middleware runs first, then store middleware, each in the order they
+//! were registered. Each one receives the state returned by the one before it. This is the same for
+//! sync and async reducers.
+//!
+//! ```
+//! use std::{any::Any, rc::Rc};
+//!
+//! use yewdux::{
+//!     middleware::{self, Change},
+//!     prelude::*,
+//! };
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! #[store(middleware = non_negative)]
+//! struct Balance {
+//!     amount: i32,
+//! }
+//!
+//! /// Reject changes that would overdraw the balance.
+//! fn non_negative(change: Change<Balance>) -> Option<Rc<Balance>> {
+//!     (change.new.amount >= 0).then_some(change.new)
+//! }
+//!
+//! fn log(change: Change<dyn Any>) -> Option<Rc<dyn Any>> {
+//!     log::info!("{} changed", change.store);
+//!     Some(change.new)
+//! }
+//!
+//! # fn main() {
+//! middleware::register_global(log);
+//!
+//! let dispatch = Dispatch::<Balance>::new();
+//! dispatch.reduce_mut(|balance| balance.amount -= 10);
+//!
+//! assert!(dispatch.get().amount == 0);
+//! # }
+//! ```
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use crate::{context::Context, mrc::Mrc, store::Store};
+
+/// A change about to be applied to a store.
+pub struct Change<S: ?Sized> {
+    /// Type name of the store.
+    pub store: &'static str,
+    /// Current state.
+    pub old: Rc<S>,
+    /// State returned by the reducer, or by the middleware before this one.
+    pub new: Rc<S>,
+}
+
+/// Runs around changes to a store. Implement `Middleware<dyn Any>` for middleware that works with
+/// any store.
+pub trait Middleware<S: ?Sized>: 'static {
+    /// Return the state to apply, or `None` to veto the change. Returning `change.new` passes it
+    /// through unchanged.
+    fn on_reduce(&self, change: Change<S>) -> Option<Rc<S>>;
+}
+
+impl<S, F> Middleware<S> for F
+where
+    S: ?Sized,
+    F: Fn(Change<S>) -> Option<Rc<S>> + 'static,
+{
+    fn on_reduce(&self, change: Change<S>) -> Option<Rc<S>> {
+        self(change)
+    }
+}
+
+type Registered<S> = Vec<(TypeId, Rc<dyn Middleware<S>>)>;
+
+/// Middleware registered in a context.
+#[derive(Default)]
+struct Registry {
+    global: Registered<dyn Any>,
+    /// Middleware for each store, as `Registered<S>` keyed by store type.
+    stores: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Store for Mrc<Registry> {
+    fn new() -> Self {
+        Registry::default().into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+fn registry(cx: &Context) -> Mrc<Registry> {
+    let entry = cx.get_or_init_internal::<Mrc<Registry>>();
+    let registry = entry.store.borrow().as_ref().clone();
+    registry
+}
+
+fn push<S: ?Sized, M: Middleware<S>>(registered: &mut Registered<S>, middleware: M) {
+    registered.push((TypeId::of::<M>(), Rc::new(middleware)));
+}
+
+/// Run middleware on changes to every store in the global context.
+pub fn register_global<M: Middleware<dyn Any>>(middleware: M) {
+    register_global_with_context(&Context::global(), middleware);
+}
+
+/// Similar to [`register_global`], but for stores in the given context.
+pub fn register_global_with_context<M: Middleware<dyn Any>>(cx: &Context, middleware: M) {
+    push(&mut registry(cx).borrow_mut().global, middleware);
+}
+
+fn with_store<S: Store, R>(cx: &Context, f: impl FnOnce(&mut Registered<S>) -> R) -> R {
+    let registry = registry(cx);
+    let mut registry = registry.borrow_mut();
+    let registered = registry
+        .stores
+        .entry(TypeId::of::<S>())
+        .or_insert_with(|| Box::<Registered<S>>::default())
+        .downcast_mut::<Registered<S>>()
+        .expect("Middleware registered for the wrong store");
+    f(registered)
+}
+
+/// Run middleware on changes to `S` in the global context.
+pub fn register<S: Store, M: Middleware<S>>(middleware: M) {
+    register_with_context::<S, M>(&Context::global(), middleware);
+}
+
+/// Similar to [`register`], but for `S` in the given context.
+pub fn register_with_context<S: Store, M: Middleware<S>>(cx: &Context, middleware: M) {
+    with_store::<S, _>(cx, |registered| push(registered, middleware));
+}
+
+/// Same as [`register_with_context`], but does nothing if middleware of the same type is already
+/// registered for `S`. This is called by the `Store` macro when using
+/// `#[store(middleware = path)]`, as `Store::new_with_context` may run again in the same context.
+#[doc(hidden)]
+pub fn register_once<S: Store, M: Middleware<S>>(cx: &Context, middleware: M) {
+    with_store::<S, _>(cx, |registered| {
+        if !registered.iter().any(|(id, _)| *id == TypeId::of::<M>()) {
+            push(registered, middleware);
+        }
+    });
+}
+
+/// Run every middleware for `S` on a change, returning the state to apply, if any.
+pub(crate) fn run<S: Store>(cx: &Context, old: Rc<S>, new: Rc<S>) -> Option<Rc<S>> {
+    // Clone the middleware out, so they can register more or change other stores.
+    let (global, store) = {
+        let registry = registry(cx);
+        let registry = registry.borrow();
+        let store = registry
+            .stores
+            .get(&TypeId::of::<S>())
+            .and_then(|registered| registered.downcast_ref::<Registered<S>>())
+            .cloned()
+            .unwrap_or_default();
+        (registry.global.clone(), store)
+    };
+
+    let mut new = new;
+    for (_, middleware) in global {
+        let change = Change::<dyn Any> {
+            store: type_name::<S>(),
+            old: Rc::clone(&old) as Rc<dyn Any>,
+            new,
+        };
+        new = middleware
+            .on_reduce(change)?
+            .downcast::<S>()
+            .unwrap_or_else(|_| {
+                panic!(
+                    "Middleware returned a different type of state for {}",
+                    type_name::<S>()
+                )
+            });
+    }
+
+    for (_, middleware) in store {
+        let change = Change {
+            store: type_name::<S>(),
+            old: Rc::clone(&old),
+            new,
+        };
+        new = middleware.on_reduce(change)?;
+    }
+
+    Some(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use crate::dispatch::{self, Dispatch};
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct TestState(i32);
+    impl Store for TestState {
+        fn new() -> Self {
+            Self(0)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    fn non_negative(change: Change<TestState>) -> Option<Rc<TestState>> {
+        (change.new.0 >= 0).then_some(change.new)
+    }
+
+    #[test]
+    fn store_middleware_can_veto() {
+        register::<TestState, _>(non_negative);
+        let calls = Rc::new(Cell::new(0));
+        let _dispatch = {
+            let calls = calls.clone();
+            Dispatch::<TestState>::subscribe_silent(move |_| calls.set(calls.get() + 1))
+        };
+
+        dispatch::set(TestState(-1));
+        assert!(dispatch::get::<TestState>().0 == 0);
+        assert!(calls.get() == 0);
+
+        dispatch::set(TestState(1));
+        assert!(dispatch::get::<TestState>().0 == 1);
+        assert!(calls.get() == 1);
+    }
+
+    #[test]
+    fn middleware_runs_in_order() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        register_global({
+            let seen = seen.clone();
+            move |change: Change<dyn Any>| {
+                seen.borrow_mut().push(format!("global {}", change.store));
+                Some(change.new)
+            }
+        });
+        register::<TestState, _>({
+            let seen = seen.clone();
+            move |change: Change<TestState>| {
+                seen.borrow_mut()
+                    .push(format!("store {} -> {}", change.old.0, change.new.0));
+                Some(Rc::new(TestState(change.new.0 * 10)))
+            }
+        });
+        register::<TestState, _>({
+            let seen = seen.clone();
+            move |change: Change<TestState>| {
+                seen.borrow_mut().push(format!("store {}", change.new.0));
+                Some(change.new)
+            }
+        });
+
+        dispatch::set(TestState(1));
+
+        assert!(dispatch::get::<TestState>().0 == 10);
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                format!("global {}", type_name::<TestState>()),
+                "store 0 -> 1".to_string(),
+                "store 10".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn middleware_of_the_same_type_is_kept() {
+        let calls = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let calls = calls.clone();
+            register::<TestState, _>(move |change: Change<TestState>| {
+                calls.set(calls.get() + 1);
+                Some(change.new)
+            });
+        }
+
+        dispatch::set(TestState(1));
+
+        assert!(calls.get() == 2);
+    }
+
+    #[test]
+    fn store_middleware_is_registered_once() {
+        let calls = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let calls = calls.clone();
+            register_once::<TestState, _>(&Context::global(), move |change: Change<TestState>| {
+                calls.set(calls.get() + 1);
+                Some(change.new)
+            });
+        }
+
+        dispatch::set(TestState(1));
+
+        assert!(calls.get() == 1);
+    }
+
+    #[test]
+    fn middleware_is_per_context() {
+        let cx = Context::new();
+        register_with_context::<TestState, _>(&cx, non_negative);
+        register_global_with_context(&cx, |_: Change<dyn Any>| None);
+        let dispatch = Dispatch::<TestState>::with_context(&cx);
+
+        dispatch::set(TestState(-1));
+        assert!(dispatch::get::<TestState>().0 == -1);
+
+        dispatch.set(TestState(1));
+        assert!(dispatch.get().0 == 0);
+    }
+
+    #[cfg(feature = "future")]
+    #[async_std::test]
+    async fn middleware_runs_for_async_reducers() {
+        register::<TestState, _>(non_negative);
+
+        dispatch::reduce_future(|_| async { Rc::new(TestState(-1)) }).await;
+
+        assert!(dispatch::get::<TestState>().0 == 0);
+    }
+}
@@ -0,0 +1,412 @@
+//! Record actions, and replay them to reproduce state.
+//!
+//! Reducers opt in by implementing [`Reducer::record`]. While recording, every such reducer
+//! applied with [`Dispatch::apply`](crate::dispatch::Dispatch::apply) is logged along with a
+//! timestamp and its store. A [`Replayer`] rebuilds state by applying the log to new stores, so a
+//! log from production can be replayed in a test.
+//!
+//! Stores and actions are identified by their [`Named::NAME`], so logs can be replayed by other
+//! builds.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use serde::{Deserialize, Serialize};
+//! use yewdux::{
+//!     prelude::*,
+//!     recorder::{self, Recorded, Replayer},
+//!     store::Named,
+//! };
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Counter {
+//!     count: u32,
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! enum Msg {
+//!     AddOne,
+//! }
+//!
+//! impl Named for Msg {
+//!     const NAME: &'static str = "counter.msg";
+//! }
+//!
+//! impl Reducer<Counter> for Msg {
+//!     fn apply(self, mut counter: Rc<Counter>) -> Rc<Counter> {
+//!         match self {
+//!             Msg::AddOne => Rc::make_mut(&mut counter).count += 1,
+//!         }
+//!
+//!         counter
+//!     }
+//!
+//!     fn record(&self) -> Option<Recorded<Counter>> {
+//!         Recorded::new(self)
+//!     }
+//! }
+//!
+//! # fn main() {
+//! recorder::start();
+//! Dispatch::<Counter>::new().apply(Msg::AddOne);
+//! let log = recorder::stop();
+//!
+//! // Later, maybe in a test.
+//! let cx = Replayer::new().action::<Counter, Msg>().replay(&log).unwrap();
+//! assert!(Dispatch::<Counter>::with_context(&cx).get().count == 1);
+//! # }
+//! ```
+use std::{any::type_name, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    context::Context,
+    mrc::Mrc,
+    store::{Named, Reducer, Store},
+};
+
+/// An action ready to be recorded, as returned by [`Reducer::record`].
+pub struct Recorded<S> {
+    store: &'static str,
+    action: &'static str,
+    value: Value,
+    _marker: PhantomData<S>,
+}
+
+impl<S: Named> Recorded<S> {
+    /// Serialize `action`, to be recorded under its name and the name of `S`. Returns `None` if it
+    /// can't be serialized.
+    pub fn new<M: Named + Serialize>(action: &M) -> Option<Self> {
+        match serde_json::to_value(action) {
+            Ok(value) => Some(Self {
+                store: S::NAME,
+                action: M::NAME,
+                value,
+                _marker: PhantomData,
+            }),
+            Err(err) => {
+                crate::log::error!("Unable to record {}: {:?}", M::NAME, err);
+                None
+            }
+        }
+    }
+}
+
+/// A recorded action.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the action was applied, in milliseconds since the Unix epoch.
+    pub timestamp: f64,
+    /// Name of the store, see [`Named`].
+    pub store: String,
+    /// Name of the action, see [`Named`].
+    pub action: String,
+    /// The action, as returned by [`Reducer::record`].
+    pub value: Value,
+}
+
+#[derive(Default)]
+struct Recording {
+    active: bool,
+    /// Incremented every time recording starts, so entries from an earlier recording aren't
+    /// removed by mistake.
+    session: u64,
+    log: Vec<Entry>,
+}
+
+impl Store for Mrc<Recording> {
+    fn new() -> Self {
+        Recording::default().into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now() -> f64 {
+    web_sys::js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs_f64() * 1000.0)
+        .unwrap_or_default()
+}
+
+impl Context {
+    fn recording(&self) -> Mrc<Recording> {
+        let entry = self.get_or_init_internal::<Mrc<Recording>>();
+        let recording = entry.store.borrow().as_ref().clone();
+        recording
+    }
+
+    /// Apply `reducer`, logging it if recording and it can be recorded. Changes vetoed by
+    /// middleware are not logged.
+    pub(crate) fn apply<S: Store, R: Reducer<S>>(&self, reducer: R) {
+        self.assert_writable::<S>();
+        // Logged before applying, so it comes before actions applied by subscribers in response.
+        let recorded = self.record::<S, R>(&reducer);
+        if !self.reduce_named(type_name::<R>(), reducer) {
+            if let Some(recorded) = recorded {
+                self.unrecord(recorded);
+            }
+        }
+    }
+
+    /// Log `reducer` if recording, and it can be recorded. Returns the session and index of the
+    /// entry.
+    fn record<S: Store, R: Reducer<S>>(&self, reducer: &R) -> Option<(u64, usize)> {
+        let recording = self.recording();
+        if !recording.borrow().active {
+            return None;
+        }
+
+        let recorded = reducer.record()?;
+        let mut recording = recording.borrow_mut();
+        recording.log.push(Entry {
+            timestamp: now(),
+            store: recorded.store.to_string(),
+            action: recorded.action.to_string(),
+            value: recorded.value,
+        });
+
+        Some((recording.session, recording.log.len() - 1))
+    }
+
+    /// Remove an entry logged by [`Self::record`], if it is still in the log.
+    fn unrecord(&self, (session, index): (u64, usize)) {
+        let recording = self.recording();
+        let mut recording = recording.borrow_mut();
+        if recording.session == session && index < recording.log.len() {
+            recording.log.remove(index);
+        }
+    }
+
+    /// Start recording actions applied in this context. Clears any previous recording.
+    pub fn start_recording(&self) {
+        let recording = self.recording();
+        let mut recording = recording.borrow_mut();
+        recording.active = true;
+        recording.session += 1;
+        recording.log.clear();
+    }
+
+    /// Stop recording, returning every action recorded.
+    pub fn stop_recording(&self) -> Vec<Entry> {
+        let recording = self.recording();
+        let mut recording = recording.borrow_mut();
+        recording.active = false;
+        std::mem::take(&mut recording.log)
+    }
+
+    /// Every action recorded so far, without stopping.
+    pub fn recorded(&self) -> Vec<Entry> {
+        self.recording().borrow().log.clone()
+    }
+}
+
+/// Start recording actions applied in the global context. See [`Context::start_recording`].
+pub fn start() {
+    Context::global().start_recording();
+}
+
+/// Stop recording actions in the global context. See [`Context::stop_recording`].
+pub fn stop() -> Vec<Entry> {
+    Context::global().stop_recording()
+}
+
+/// Every action recorded so far in the global context. See [`Context::recorded`].
+pub fn recorded() -> Vec<Entry> {
+    Context::global().recorded()
+}
+
+/// An error replaying a log.
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("Action {action} for {store} is not known to the replayer")]
+    UnknownAction { store: String, action: String },
+    #[error("A serde error occurred")]
+    Serde(#[from] serde_json::Error),
+}
+
+type Apply = fn(&Context, Value) -> Result<(), serde_json::Error>;
+
+/// Rebuilds state from a log of actions. Every action type in the log must be added with
+/// [`Replayer::action`].
+#[derive(Default)]
+pub struct Replayer {
+    /// `(store, action, apply)` for every known action.
+    actions: Vec<(&'static str, &'static str, Apply)>,
+}
+
+impl Replayer {
+    /// Create a replayer that knows no actions yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow replaying actions of type `M` on `S`, matched by their [names](Named).
+    pub fn action<S, M>(mut self) -> Self
+    where
+        S: Store + Named,
+        M: Reducer<S> + Named + DeserializeOwned,
+    {
+        fn apply<S: Store, M: Reducer<S> + DeserializeOwned>(
+            cx: &Context,
+            value: Value,
+        ) -> Result<(), serde_json::Error> {
+            let action = serde_json::from_value::<M>(value)?;
+            cx.reduce(action);
+            Ok(())
+        }
+
+        self.actions.push((S::NAME, M::NAME, apply::<S, M>));
+        self
+    }
+
+    /// Apply the log to a new context, with every store starting from [`Store::new`].
+    pub fn replay(&self, log: &[Entry]) -> Result<Context, ReplayError> {
+        let cx = Context::new();
+        self.replay_with_context(&cx, log)?;
+
+        Ok(cx)
+    }
+
+    /// Apply the log to the given context.
+    pub fn replay_with_context(&self, cx: &Context, log: &[Entry]) -> Result<(), ReplayError> {
+        for entry in log {
+            let (.., apply) = self
+                .actions
+                .iter()
+                .find(|(store, action, _)| *store == entry.store && *action == entry.action)
+                .ok_or_else(|| ReplayError::UnknownAction {
+                    store: entry.store.clone(),
+                    action: entry.action.clone(),
+                })?;
+
+            apply(cx, entry.value.clone())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{dispatch::Dispatch, test_util::TestState};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    enum Msg {
+        Add(u32),
+        Double,
+    }
+
+    impl Named for Msg {
+        const NAME: &'static str = "msg";
+    }
+
+    impl Reducer<TestState> for Msg {
+        fn apply(self, state: Rc<TestState>) -> Rc<TestState> {
+            match self {
+                Msg::Add(n) => TestState(state.0 + n),
+                Msg::Double => TestState(state.0 * 2),
+            }
+            .into()
+        }
+
+        fn record(&self) -> Option<Recorded<TestState>> {
+            Recorded::new(self)
+        }
+    }
+
+    #[test]
+    fn records_serializable_actions() {
+        let cx = Context::new();
+        let dispatch = Dispatch::<TestState>::with_context(&cx);
+
+        dispatch.apply(Msg::Add(1));
+        cx.start_recording();
+        dispatch.apply(Msg::Add(2));
+        dispatch.apply(|state: Rc<TestState>| TestState(state.0 + 1).into());
+        dispatch.apply_callback(|_| Msg::Double).emit(());
+        let log = cx.stop_recording();
+        dispatch.apply(Msg::Add(3));
+
+        let actions: Vec<_> = log.iter().map(|entry| entry.value.clone()).collect();
+        assert_eq!(
+            actions,
+            vec![serde_json::json!({ "Add": 2 }), serde_json::json!("Double")]
+        );
+        assert!(log
+            .iter()
+            .all(|entry| entry.store == "TestState" && entry.action == "msg"));
+        assert!(cx.recorded().is_empty());
+    }
+
+    #[test]
+    fn vetoed_actions_are_not_recorded() {
+        let cx = Context::new();
+        crate::middleware::register_with_context::<TestState, _>(
+            &cx,
+            |change: crate::middleware::Change<TestState>| {
+                (change.new.0 < 10).then_some(change.new)
+            },
+        );
+        let dispatch = Dispatch::<TestState>::with_context(&cx);
+
+        cx.start_recording();
+        dispatch.apply(Msg::Add(1));
+        dispatch.apply(Msg::Add(10));
+        dispatch.apply(Msg::Double);
+        let log = cx.stop_recording();
+
+        let actions: Vec<_> = log.iter().map(|entry| entry.value.clone()).collect();
+        assert_eq!(
+            actions,
+            vec![serde_json::json!({ "Add": 1 }), serde_json::json!("Double")]
+        );
+    }
+
+    #[test]
+    fn replay_rebuilds_state() {
+        let cx = Context::new();
+        let dispatch = Dispatch::<TestState>::with_context(&cx);
+        cx.start_recording();
+        dispatch.apply(Msg::Add(2));
+        dispatch.apply(Msg::Double);
+        dispatch.apply(Msg::Add(1));
+        let log = cx.stop_recording();
+
+        // Round trip through JSON, as if loaded from a bug report.
+        let log: Vec<Entry> = serde_json::from_str(&serde_json::to_string(&log).unwrap()).unwrap();
+        let replayed = Replayer::new()
+            .action::<TestState, Msg>()
+            .replay(&log)
+            .unwrap();
+
+        assert!(Dispatch::<TestState>::with_context(&replayed).get().0 == 5);
+        assert!(Dispatch::<TestState>::with_context(&replayed).get() == dispatch.get());
+    }
+
+    #[test]
+    fn replay_fails_on_unknown_action() {
+        let cx = Context::new();
+        cx.start_recording();
+        Dispatch::<TestState>::with_context(&cx).apply(Msg::Add(1));
+        let log = cx.stop_recording();
+
+        let result = Replayer::new().replay(&log);
+
+        assert!(matches!(result, Err(ReplayError::UnknownAction { .. })));
+    }
+}
@@ -0,0 +1,425 @@
+//! Inspect which stores exist, and take snapshots of their state.
+//!
+//! Every store is listed along with its subscriber count. Stores that opt in with the `snapshot`
+//! attribute (or by calling [`register`] from `Store::new`) are also serialized, so their state
+//! can be saved with [`snapshot_all`] and loaded again with [`restore_all`]. Snapshots are keyed by
+//! the [name](Named) of each store, so they can be restored by other builds.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use yewdux::{prelude::*, registry};
+//!
+//! #[derive(Default, Clone, PartialEq, Serialize, Deserialize, Store)]
+//! #[store(snapshot)]
+//! struct Counter {
+//!     count: u32,
+//! }
+//!
+//! # fn main() {
+//! Dispatch::<Counter>::new().reduce_mut(|counter| counter.count = 1);
+//!
+//! // Attach this to a bug report.
+//! let json = serde_json::to_string(&registry::snapshot_all()).unwrap();
+//!
+//! // ...and load it again later.
+//! Dispatch::<Counter>::new().reset();
+//! registry::restore_all(&serde_json::from_str(&json).unwrap()).unwrap();
+//!
+//! assert!(Dispatch::<Counter>::new().get().count == 1);
+//! # }
+//! ```
+use std::{
+    any::{type_name, TypeId},
+    cell::RefCell,
+    rc::Rc,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    context::{Context, Registered},
+    mrc::Mrc,
+    store::{Named, Store},
+};
+
+pub(crate) type SerializeFn = fn(&Context) -> Option<Result<Value, serde_json::Error>>;
+type Restore = Box<dyn FnOnce(&Context)>;
+type DeserializeFn = fn(Value) -> Result<Restore, serde_json::Error>;
+
+/// How to serialize a store type, shared by snapshots and [hydration](crate::hydration).
+#[derive(Clone)]
+pub(crate) struct Serde {
+    type_id: TypeId,
+    pub(crate) type_name: &'static str,
+    /// See [`Named`].
+    pub(crate) name: &'static str,
+    pub(crate) serialize: SerializeFn,
+    /// Set for stores included in snapshots.
+    deserialize: Option<DeserializeFn>,
+    /// Whether the store is included in hydration.
+    pub(crate) hydrate: bool,
+}
+
+thread_local! {
+    /// Every store type that opted in to snapshots or hydration.
+    static SERDE: RefCell<Vec<Serde>> = Default::default();
+}
+
+/// Every store type that opted in to snapshots or hydration, in order of registration.
+pub(crate) fn serde_all() -> Vec<Serde> {
+    SERDE
+        .try_with(|serde| serde.borrow().clone())
+        .expect("SERDE thread local key init failed")
+}
+
+/// Stores included in snapshots only.
+fn snapshot_for(find: impl Fn(&Serde) -> bool) -> Option<(Serde, DeserializeFn)> {
+    SERDE
+        .try_with(|serde| {
+            serde
+                .borrow()
+                .iter()
+                .filter(|serde| find(serde))
+                .find_map(|serde| Some((serde.clone(), serde.deserialize?)))
+        })
+        .expect("SERDE thread local key init failed")
+}
+
+/// Add `S` to the registry if it isn't there yet, then update its entry.
+pub(crate) fn register_serde<S: Store + Serialize + Named>(update: impl FnOnce(&mut Serde)) {
+    fn serialize<S: Store + Serialize>(cx: &Context) -> Option<Result<Value, serde_json::Error>> {
+        cx.try_get::<S>()
+            .map(|state| serde_json::to_value(state.as_ref()))
+    }
+
+    SERDE
+        .try_with(|serde| {
+            let mut serde = serde.borrow_mut();
+            let index = match serde
+                .iter()
+                .position(|serde| serde.type_id == TypeId::of::<S>())
+            {
+                Some(index) => index,
+                None => {
+                    serde.push(Serde {
+                        type_id: TypeId::of::<S>(),
+                        type_name: type_name::<S>(),
+                        name: S::NAME,
+                        serialize: serialize::<S>,
+                        deserialize: None,
+                        hydrate: false,
+                    });
+                    serde.len() - 1
+                }
+            };
+            update(&mut serde[index]);
+        })
+        .expect("SERDE thread local key init failed");
+}
+
+/// Include a store in snapshots. This is called by the `Store` macro when using
+/// `#[store(snapshot)]`.
+pub fn register<S: Store + Serialize + DeserializeOwned + Named>() {
+    fn deserialize<S: Store + DeserializeOwned>(
+        value: Value,
+    ) -> Result<Restore, serde_json::Error> {
+        let state = Rc::new(serde_json::from_value::<S>(value)?);
+        Ok(Box::new(move |cx| cx.reduce_unchecked(move |_| state)))
+    }
+
+    register_serde::<S>(|serde| serde.deserialize = Some(deserialize::<S>));
+}
+
+/// Information about an initialized store.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreInfo {
+    /// Type name of the store.
+    pub type_name: &'static str,
+    /// Number of active subscribers.
+    pub subscribers: usize,
+    /// Current state, if the store is included in snapshots.
+    pub value: Option<Value>,
+}
+
+/// State of every store included in snapshots, keyed by [name](Named).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Snapshot(pub Map<String, Value>);
+
+/// Stores restored before they were created, keyed by name.
+struct Pending(Map<String, Value>);
+impl Store for Mrc<Pending> {
+    fn new() -> Self {
+        Pending(Map::new()).into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+impl Context {
+    /// Every initialized store that isn't internal, along with how to serialize it if it is
+    /// included in snapshots.
+    fn listed(&self) -> Vec<(Registered, Option<Serde>)> {
+        self.registered()
+            .into_iter()
+            // Skip stores that were dropped, see `Store::retain`.
+            .filter(|store| !store.internal && (store.initialized)(self))
+            .map(|store| {
+                let serde = snapshot_for(|serde| serde.type_id == store.type_id);
+                (store, serde.map(|(serde, _)| serde))
+            })
+            .collect()
+    }
+
+    fn serialize(&self, serde: &Serde) -> Option<Value> {
+        match (serde.serialize)(self)? {
+            Ok(value) => Some(value),
+            Err(err) => {
+                crate::log::error!("Unable to serialize {}: {:?}", serde.type_name, err);
+                None
+            }
+        }
+    }
+
+    /// Every initialized store in this context, in order of creation.
+    pub fn stores(&self) -> Vec<StoreInfo> {
+        self.listed()
+            .into_iter()
+            .map(|(store, serde)| StoreInfo {
+                type_name: store.type_name,
+                subscribers: (store.subscribers)(self),
+                value: serde.and_then(|serde| self.serialize(&serde)),
+            })
+            .collect()
+    }
+
+    /// Serialize every initialized store that is included in snapshots.
+    pub fn snapshot_all(&self) -> Snapshot {
+        let map = self
+            .listed()
+            .into_iter()
+            .filter_map(|(_, serde)| {
+                let serde = serde?;
+                Some((serde.name.to_string(), self.serialize(&serde)?))
+            })
+            .collect();
+
+        Snapshot(map)
+    }
+
+    /// Load state from a snapshot. Stores that don't exist yet are loaded when they are created.
+    ///
+    /// If any store fails to deserialize nothing is changed. Otherwise subscribers are notified
+    /// once every store is restored.
+    pub fn restore_all(&self, snapshot: &Snapshot) -> Result<(), serde_json::Error> {
+        let registered = self.registered();
+        let is_initialized = |type_id| {
+            registered
+                .iter()
+                .any(|store| store.type_id == type_id && (store.initialized)(self))
+        };
+
+        let mut restores = Vec::new();
+        let mut pending = Map::new();
+        for (name, value) in &snapshot.0 {
+            let serde = snapshot_for(|serde| serde.name == name);
+            match serde {
+                Some((serde, deserialize)) if is_initialized(serde.type_id) => {
+                    restores.push(deserialize(value.clone())?);
+                }
+                Some((_, deserialize)) => {
+                    // Make sure it's valid now, rather than failing when the store is created.
+                    let _ = deserialize(value.clone())?;
+                    pending.insert(name.clone(), value.clone());
+                }
+                None => {
+                    pending.insert(name.clone(), value.clone());
+                }
+            }
+        }
+
+        self.get_or_init_internal::<Mrc<Pending>>()
+            .store
+            .borrow()
+            .borrow_mut()
+            .0
+            .extend(pending);
+        self.batch(|| {
+            for restore in restores {
+                restore(self);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Load `S` from a snapshot restored before it was created.
+    pub(crate) fn restore_pending<S: Store>(&self) {
+        let pending = match self.try_get::<Mrc<Pending>>() {
+            Some(pending) => pending,
+            None => return,
+        };
+        // Only stores included in snapshots have a name to be found by.
+        let (serde, deserialize) = match snapshot_for(|serde| serde.type_id == TypeId::of::<S>()) {
+            Some(found) => found,
+            None => return,
+        };
+        let value = match pending.borrow_mut().0.remove(serde.name) {
+            Some(value) => value,
+            None => return,
+        };
+
+        match deserialize(value) {
+            Ok(restore) => restore(self),
+            Err(err) => {
+                crate::log::error!("Unable to restore {}: {:?}", type_name::<S>(), err);
+            }
+        }
+    }
+}
+
+/// Every initialized store in the global context. See [`Context::stores`].
+pub fn stores() -> Vec<StoreInfo> {
+    Context::global().stores()
+}
+
+/// Serialize every store in the global context that is included in snapshots. See
+/// [`Context::snapshot_all`].
+pub fn snapshot_all() -> Snapshot {
+    Context::global().snapshot_all()
+}
+
+/// Load state into the global context from a snapshot. See [`Context::restore_all`].
+pub fn restore_all(snapshot: &Snapshot) -> Result<(), serde_json::Error> {
+    Context::global().restore_all(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{dispatch::Dispatch, test_util::number_stores};
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct Counter(u32);
+    impl Named for Counter {
+        const NAME: &'static str = "counter";
+    }
+    impl Store for Counter {
+        fn new() -> Self {
+            register::<Self>();
+            Self(0)
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    // Not serializable, so not in snapshots.
+    number_stores!(Opaque = 0);
+
+    #[test]
+    fn stores_lists_initialized_stores() {
+        let cx = Context::new();
+        assert!(cx.stores().is_empty());
+
+        cx.set(Counter(1));
+        let _dispatch = Dispatch::<Opaque>::subscribe_with_context(&cx, |_| {});
+
+        assert_eq!(
+            cx.stores(),
+            vec![
+                StoreInfo {
+                    type_name: type_name::<Counter>(),
+                    subscribers: 0,
+                    value: Some(json!(1)),
+                },
+                StoreInfo {
+                    type_name: type_name::<Opaque>(),
+                    subscribers: 1,
+                    value: None,
+                },
+            ]
+        );
+    }
+
+    impl crate::keyed::KeyedStore for Opaque {
+        type Key = u32;
+
+        fn new(key: &u32) -> Self {
+            Self(*key)
+        }
+    }
+
+    #[test]
+    fn internal_stores_are_not_listed() {
+        let cx = Context::new();
+        let _dispatch = Dispatch::<Opaque>::keyed_with_context(&cx, 1);
+
+        assert!(cx.stores().is_empty());
+    }
+
+    #[test]
+    fn hydrated_stores_can_be_included_in_snapshots() {
+        crate::hydration::register::<Counter>();
+        let cx = Context::new();
+        cx.set(Counter(1));
+
+        assert_eq!(cx.snapshot_all().0.len(), 1);
+        assert_eq!(crate::hydration::to_json(&cx), json!({ "counter": 1 }));
+    }
+
+    #[test]
+    fn snapshot_is_keyed_by_store_name() {
+        let cx = Context::new();
+        cx.set(Counter(1));
+
+        assert_eq!(
+            cx.snapshot_all().0,
+            json!({ "counter": 1 }).as_object().unwrap().clone()
+        );
+    }
+
+    #[test]
+    fn snapshot_can_be_restored() {
+        let cx = Context::new();
+        cx.set(Counter(1));
+        cx.set(Opaque(1));
+
+        let snapshot = cx.snapshot_all();
+        assert_eq!(snapshot.0.len(), 1);
+
+        cx.set(Counter(2));
+        cx.restore_all(&snapshot).unwrap();
+        assert!(cx.get::<Counter>().0 == 1);
+    }
+
+    #[test]
+    fn snapshot_is_restored_when_store_is_created() {
+        let cx = Context::new();
+        cx.set(Counter(1));
+        let snapshot = cx.snapshot_all();
+
+        let other = Context::new();
+        other.restore_all(&snapshot).unwrap();
+        assert!(other.get::<Counter>().0 == 1);
+    }
+
+    #[test]
+    fn invalid_snapshot_changes_nothing() {
+        let cx = Context::new();
+        cx.set(Counter(1));
+
+        let mut snapshot = cx.snapshot_all();
+        snapshot.0.insert("counter".to_string(), json!("nope"));
+
+        assert!(cx.restore_all(&snapshot).is_err());
+        assert!(cx.get::<Counter>().0 == 1);
+    }
+}
@@ -0,0 +1,317 @@
+//! Selectors shared between components.
+//!
+//! Every [`use_selector`](crate::functional::use_selector) runs its own selector whenever the
+//! store changes. A [`Selector`] is declared once instead, and its result is cached for the
+//! current state of the store and each set of dependencies. Components using the same selector
+//! with the same dependencies share one computation, and one [`Rc`] of the result.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use yew::prelude::*;
+//! use yewdux::{
+//!     prelude::*,
+//!     selector::{use_shared_selector, Selector},
+//! };
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Todos {
+//!     items: Vec<(String, bool)>,
+//! }
+//!
+//! struct Remaining;
+//! impl Selector for Remaining {
+//!     type Store = Todos;
+//!     type Deps = ();
+//!     type Output = usize;
+//!
+//!     fn select(todos: &Todos, _: &()) -> usize {
+//!         todos.items.iter().filter(|(_, done)| !done).count()
+//!     }
+//! }
+//!
+//! #[function_component]
+//! fn Counter() -> Html {
+//!     let remaining = use_shared_selector::<Remaining>(());
+//!
+//!     html! {
+//!         <p>{ format!("{remaining} left") }</p>
+//!     }
+//! }
+//! ```
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    rc::{Rc, Weak},
+};
+
+use yew::functional::*;
+
+use crate::{
+    context::Context, dispatch::Dispatch, functional::use_selected, mrc::Mrc, store::Store,
+};
+
+/// A selector declared once and shared by everyone using it.
+pub trait Selector: 'static {
+    /// The store to select from.
+    type Store: Store;
+    /// Dependencies the result is also computed from. Results are cached for each value.
+    type Deps: Clone + Eq + Hash + 'static;
+    /// The selected value.
+    type Output: PartialEq + 'static;
+
+    /// Select the value from the current state of the store.
+    fn select(state: &Self::Store, deps: &Self::Deps) -> Self::Output;
+}
+
+/// How often a [`Selector`] was served from its cache. Useful for tuning which selectors are worth
+/// sharing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Times the cached result was returned.
+    pub hits: u64,
+    /// Times the result was computed.
+    pub misses: u64,
+}
+
+/// Results of `Sel`, for the state they were computed from.
+struct Cache<Sel: Selector> {
+    /// Doesn't keep old state alive, but keeps its address from being reused.
+    version: Weak<Sel::Store>,
+    values: HashMap<Sel::Deps, Rc<Sel::Output>>,
+    stats: Stats,
+}
+
+impl<Sel: Selector> Cache<Sel> {
+    fn get(&mut self, state: &Rc<Sel::Store>, deps: &Sel::Deps) -> Option<Rc<Sel::Output>> {
+        if self.version.as_ptr() != Rc::as_ptr(state) {
+            // Every result is stale once the state changes.
+            self.version = Rc::downgrade(state);
+            self.values.clear();
+        }
+
+        let value = self.values.get(deps).cloned();
+        match value {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+
+        value
+    }
+}
+
+impl<Sel: Selector> Store for Mrc<Cache<Sel>> {
+    fn new() -> Self {
+        Cache::<Sel> {
+            version: Weak::new(),
+            values: HashMap::new(),
+            stats: Stats::default(),
+        }
+        .into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+fn select<Sel: Selector>(
+    cache: &Mrc<Cache<Sel>>,
+    state: &Rc<Sel::Store>,
+    deps: &Sel::Deps,
+) -> Rc<Sel::Output> {
+    if let Some(value) = cache.borrow_mut().get(state, deps) {
+        return value;
+    }
+
+    // Compute outside of borrow, in case the selector is used recursively.
+    let value = Rc::new(Sel::select(state, deps));
+    let mut cache = cache.borrow_mut();
+    if cache.version.as_ptr() == Rc::as_ptr(state) {
+        cache.values.insert(deps.clone(), Rc::clone(&value));
+    }
+
+    value
+}
+
+impl Context {
+    fn selector_cache<Sel: Selector>(&self) -> Mrc<Cache<Sel>> {
+        let entry = self.get_or_init_internal::<Mrc<Cache<Sel>>>();
+        let cache = entry.store.borrow().as_ref().clone();
+        cache
+    }
+
+    pub(crate) fn select<Sel: Selector>(&self, deps: &Sel::Deps) -> Rc<Sel::Output> {
+        select(&self.selector_cache::<Sel>(), &self.get(), deps)
+    }
+
+    pub(crate) fn selector_stats<Sel: Selector>(&self) -> Stats {
+        self.selector_cache::<Sel>().borrow().stats
+    }
+}
+
+impl<S: Store> Dispatch<S> {
+    /// Select a value with a shared [`Selector`], reusing the cached result if there is one.
+    pub fn select<Sel: Selector<Store = S>>(&self, deps: &Sel::Deps) -> Rc<Sel::Output> {
+        self.context().select::<Sel>(deps)
+    }
+
+    /// How often `Sel` was served from its cache in this context.
+    pub fn selector_stats<Sel: Selector<Store = S>>(&self) -> Stats {
+        self.context().selector_stats::<Sel>()
+    }
+}
+
+/// Similar to [`use_selector_with_deps`](crate::functional::use_selector_with_deps), with a
+/// shared [`Selector`]. Components using the same selector and dependencies share the result.
+///
+/// The selector is used when the component is created, and when the store or dependencies
+/// change. Rendering again for other reasons doesn't count towards its [`Stats`].
+///
+/// # Example
+/// ```
+/// # use std::collections::HashMap;
+/// # use yew::prelude::*;
+/// # use yewdux::{prelude::*, selector::{use_shared_selector, Selector}};
+/// # #[derive(Default, Clone, PartialEq, Store)]
+/// # struct Items {
+/// #     names: HashMap<u32, String>,
+/// # }
+/// struct ItemName;
+/// impl Selector for ItemName {
+///     type Store = Items;
+///     type Deps = u32;
+///     type Output = Option<String>;
+///
+///     fn select(items: &Items, id: &u32) -> Option<String> {
+///         items.names.get(id).cloned()
+///     }
+/// }
+///
+/// #[derive(Properties, PartialEq)]
+/// struct Props {
+///     id: u32,
+/// }
+///
+/// #[function_component]
+/// fn Row(props: &Props) -> Html {
+///     let name = use_shared_selector::<ItemName>(props.id);
+///
+///     html! {
+///         <li>{ name.as_deref().unwrap_or_default() }</li>
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_shared_selector<Sel>(deps: Sel::Deps) -> Rc<Sel::Output>
+where
+    Sel: Selector,
+{
+    use_selected(
+        |cx, deps| cx.select::<Sel>(deps),
+        |a, b| a == b,
+        deps,
+        |cx, _, on_change| {
+            Dispatch::<Sel::Store>::subscribe_silent_with_context(cx, move |_| on_change())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::test_util::isolated;
+
+    use super::*;
+
+    thread_local! {
+        static CALLS: Cell<u32> = Default::default();
+    }
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Items(Vec<u32>);
+    impl Store for Items {
+        fn new() -> Self {
+            Self(vec![1, 2, 3])
+        }
+
+        fn should_notify(&self, other: &Self) -> bool {
+            self != other
+        }
+    }
+
+    /// Items greater than the given value.
+    struct Above;
+    impl Selector for Above {
+        type Store = Items;
+        type Deps = u32;
+        type Output = Vec<u32>;
+
+        fn select(items: &Items, min: &u32) -> Vec<u32> {
+            CALLS.with(|calls| calls.set(calls.get() + 1));
+            items.0.iter().copied().filter(|item| item > min).collect()
+        }
+    }
+
+    fn setup() -> Dispatch<Items> {
+        CALLS.with(|calls| calls.set(0));
+
+        isolated()
+    }
+
+    #[test]
+    fn results_are_shared() {
+        let dispatch = setup();
+
+        let first = dispatch.select::<Above>(&1);
+        let second = dispatch.select::<Above>(&1);
+
+        assert_eq!(*first, vec![2, 3]);
+        assert!(Rc::ptr_eq(&first, &second));
+        assert!(CALLS.with(Cell::get) == 1);
+        assert_eq!(
+            dispatch.selector_stats::<Above>(),
+            Stats { hits: 1, misses: 1 }
+        );
+    }
+
+    #[test]
+    fn results_are_cached_per_deps() {
+        let dispatch = setup();
+
+        assert_eq!(*dispatch.select::<Above>(&1), vec![2, 3]);
+        assert_eq!(*dispatch.select::<Above>(&2), vec![3]);
+        dispatch.select::<Above>(&1);
+
+        assert!(CALLS.with(Cell::get) == 2);
+    }
+
+    #[test]
+    fn results_are_recomputed_when_state_changes() {
+        let dispatch = setup();
+        let before = dispatch.select::<Above>(&1);
+
+        dispatch.reduce_mut(|items| items.0.push(4));
+        let after = dispatch.select::<Above>(&1);
+
+        assert_eq!(*after, vec![2, 3, 4]);
+        assert!(!Rc::ptr_eq(&before, &after));
+        assert_eq!(
+            dispatch.selector_stats::<Above>(),
+            Stats { hits: 0, misses: 2 }
+        );
+    }
+
+    #[test]
+    fn caches_are_per_context() {
+        let dispatch = setup();
+        dispatch.select::<Above>(&1);
+
+        let other = Dispatch::<Items>::with_context(&Context::new());
+        other.select::<Above>(&1);
+
+        assert!(CALLS.with(Cell::get) == 2);
+        assert_eq!(other.selector_stats::<Above>().misses, 1);
+    }
+}
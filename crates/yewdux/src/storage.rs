@@ -45,7 +45,7 @@ use serde::{de::DeserializeOwned, Serialize};
 use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
 use web_sys::{Event, Storage};
 
-use crate::{dispatch::Dispatch, listener::Listener, store::Store};
+use crate::{context::Context, dispatch::Dispatch, listener::Listener, store::Store};
 
 #[derive(Debug, thiserror::Error)]
 pub enum StorageError {
@@ -93,7 +93,7 @@ where
     }
 }
 
-fn get_storage(area: Area) -> Result<Storage, StorageError> {
+pub(crate) fn get_storage(area: Area) -> Result<Storage, StorageError> {
     let window = web_sys::window().ok_or(StorageError::WindowNotFound)?;
     let storage = match area {
         Area::Local => window.local_storage(),
@@ -135,12 +135,35 @@ pub fn load<T: DeserializeOwned>(area: Area) -> Result<Option<T>, StorageError>
     }
 }
 
+/// Remove saved state from session or local storage.
+pub fn clear<T>(area: Area) -> Result<(), StorageError> {
+    let storage = get_storage(area)?;
+
+    storage
+        .remove_item(type_name::<T>())
+        .map_err(StorageError::WebSys)?;
+
+    Ok(())
+}
+
 /// Synchronize state across all tabs. **WARNING**: This provides no protection for multiple
 /// calls. Doing so will result in repeated loading. Using the macro is advised.
 pub fn init_tab_sync<S: Store + DeserializeOwned>(area: Area) -> Result<(), StorageError> {
+    init_tab_sync_with_context::<S>(&Context::global(), area)
+}
+
+/// Similar to [init_tab_sync], but updates the store in the given context. Syncing stops once the
+/// context is dropped.
+pub fn init_tab_sync_with_context<S: Store + DeserializeOwned>(
+    cx: &Context,
+    area: Area,
+) -> Result<(), StorageError> {
+    let cx = cx.downgrade();
     let closure = Closure::wrap(Box::new(move |_: &Event| match load(area) {
         Ok(Some(state)) => {
-            Dispatch::<S>::new().set(state);
+            if let Some(cx) = cx.upgrade() {
+                Dispatch::<S>::with_context(&cx).set(state);
+            }
         }
         Err(e) => {
             crate::log::error!("Unable to load state: {:?}", e);
@@ -2,17 +2,109 @@
 use std::{future::Future, rc::Rc};
 
 use async_trait::async_trait;
-
 pub use yewdux_macros::Store;
 
+use crate::{context::Context, recorder::Recorded};
+
 /// Globally shared state.
 pub trait Store: 'static {
     /// Create this store.
     fn new() -> Self;
 
+    /// Create this store in the given context. Defaults to [`Store::new`]. Override this when
+    /// creating the store depends on other stores in the same context.
+    fn new_with_context(cx: &Context) -> Self
+    where
+        Self: Sized,
+    {
+        let _ = cx;
+        Self::new()
+    }
+
+    /// Create the value this store is reset to, with [`Dispatch::reset`] or
+    /// [`Context::reset_all`]. Defaults to [`Store::new_with_context`].
+    ///
+    /// The macro resets to `Default::default()` instead, so setup done in `new`, such as reading
+    /// storage or registering listeners, isn't repeated. Stores persisted with
+    /// `#[store(storage = ...)]` also clear their storage key, so resetting wipes the saved
+    /// state.
+    ///
+    /// [`Dispatch::reset`]: crate::dispatch::Dispatch::reset
+    fn reset(cx: &Context) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new_with_context(cx)
+    }
+
     /// Indicate whether or not subscribers should be notified about this change. Usually this
     /// should be set to `self != old`.
     fn should_notify(&self, old: &Self) -> bool;
+
+    /// Whether to keep this store once nothing is using it. Defaults to `true`.
+    ///
+    /// When this returns `false`, the store is dropped as soon as it has no subscribers and no
+    /// [`Dispatch`](crate::dispatch::Dispatch) for it is held. It is created again with
+    /// [`Store::new`] on next access.
+    ///
+    /// With the macro, use `#[store(drop_when_unused)]`. Cleanup can be done with `on_drop`.
+    ///
+    /// ```
+    /// use yewdux::prelude::*;
+    ///
+    /// #[derive(Default, Clone, PartialEq, Store)]
+    /// #[store(drop_when_unused, on_drop = clear_cache)]
+    /// struct Page {
+    ///     rows: Vec<String>,
+    /// }
+    ///
+    /// fn clear_cache(_page: &Page) {
+    ///     // ...
+    /// }
+    ///
+    /// # fn main() {
+    /// let dispatch = Dispatch::<Page>::new();
+    /// dispatch.reduce_mut(|page| page.rows.push("row".into()));
+    ///
+    /// // Dropping the last dispatch drops the store too.
+    /// drop(dispatch);
+    ///
+    /// assert!(Dispatch::<Page>::new().get().rows.is_empty());
+    /// # }
+    /// ```
+    fn retain(&self) -> bool {
+        true
+    }
+
+    /// Called when this store is dropped because it is no longer used. See [`Store::retain`].
+    fn on_drop(&self) {}
+}
+
+/// A name identifying a type across builds.
+///
+/// Unlike [`std::any::type_name`], which may differ between compiler versions and targets, this
+/// is chosen by you. It is used to match saved data with its type, such as state sent from the
+/// server for [hydration](crate::hydration), [snapshots](crate::registry) and
+/// [recorded](crate::recorder) actions. Names must be unique among the types they're used with.
+///
+/// The `Store` macro implements this with the name of the type. Use `#[store(name = "...")]` to
+/// choose another.
+///
+/// ```
+/// use yewdux::{prelude::*, store::Named};
+///
+/// #[derive(Default, Clone, PartialEq, Store)]
+/// #[store(name = "settings.v2")]
+/// struct Settings {
+///     dark: bool,
+/// }
+///
+/// # fn main() {
+/// assert_eq!(Settings::NAME, "settings.v2");
+/// # }
+/// ```
+pub trait Named {
+    const NAME: &'static str;
 }
 
 /// A type that can change state.
@@ -59,6 +151,14 @@ pub trait Store: 'static {
 pub trait Reducer<S> {
     /// Mutate state.
     fn apply(self, state: Rc<S>) -> Rc<S>;
+
+    /// Serialize this reducer, so it can be recorded when applied with
+    /// [`Dispatch::apply`](crate::dispatch::Dispatch::apply). Defaults to `None`, meaning it isn't
+    /// recorded. Usually implemented with [`Recorded::new`]. See the [recorder](crate::recorder)
+    /// module.
+    fn record(&self) -> Option<Recorded<S>> {
+        None
+    }
 }
 
 impl<F, S> Reducer<S> for F
@@ -1,17 +1,58 @@
 use std::rc::Rc;
-use std::{any::Any, marker::PhantomData};
+use std::{any::Any, collections::VecDeque, marker::PhantomData};
 
 use slab::Slab;
 use yew::Callback;
 
+use crate::context::{Context, WeakContext};
 use crate::mrc::Mrc;
 use crate::store::Store;
 
-pub(crate) struct Subscribers<S>(pub(crate) Slab<Box<dyn Callable<S>>>);
+pub(crate) struct Subscribers<S>(pub(crate) Slab<Rc<dyn Callable<S>>>, Notifying<S>);
+
+/// Tracks notifications in progress, so subscribers can be added and removed while being notified.
+pub(crate) struct Notifying<S> {
+    active: bool,
+    /// Depth of the state being sent, see [`Subscribers::next_depth`].
+    depth: usize,
+    /// States waiting to be sent, in order, along with their depth. Changes made by subscribers
+    /// are queued here, so every subscriber sees them in order.
+    queue: VecDeque<(Rc<S>, usize)>,
+    /// Subscribers removed while notifying. They are skipped, and removed once done.
+    removed: Vec<usize>,
+}
+
+impl<S> Default for Notifying<S> {
+    fn default() -> Self {
+        Self {
+            active: false,
+            depth: 0,
+            queue: Default::default(),
+            removed: Default::default(),
+        }
+    }
+}
+
+impl<S> Subscribers<S> {
+    /// Number of active subscribers.
+    pub(crate) fn len(&self) -> usize {
+        self.0.len() - self.1.removed.len()
+    }
+
+    /// How deeply the next state is nested in notifications of this store. This is 1, or one more
+    /// than the state being sent when a subscriber changes the store again in response.
+    pub(crate) fn next_depth(&self) -> usize {
+        if self.1.active {
+            self.1.depth + 1
+        } else {
+            1
+        }
+    }
+}
 
 impl<S: 'static> Store for Subscribers<S> {
     fn new() -> Self {
-        Self(Default::default())
+        Self(Default::default(), Default::default())
     }
 
     fn should_notify(&self, other: &Self) -> bool {
@@ -20,22 +61,85 @@ impl<S: 'static> Store for Subscribers<S> {
 }
 
 impl<S: Store> Mrc<Subscribers<S>> {
-    pub(crate) fn subscribe<C: Callable<S>>(&self, on_change: C) -> SubscriberId<S> {
-        let key = self.borrow_mut().0.insert(Box::new(on_change));
+    pub(crate) fn subscribe<C: Callable<S>>(&self, cx: &Context, on_change: C) -> SubscriberId<S> {
+        let key = self.borrow_mut().0.insert(Rc::new(on_change));
         SubscriberId {
             subscribers_ref: self.clone(),
+            cx: cx.downgrade(),
             key,
             _store_type: Default::default(),
         }
     }
 
     pub(crate) fn unsubscribe(&mut self, key: usize) {
-        self.borrow_mut().0.remove(key);
+        let mut subscribers = self.borrow_mut();
+        if subscribers.1.active {
+            // Keep the key taken until notifying is done, so it isn't reused by a new subscriber.
+            subscribers.1.removed.push(key);
+        } else {
+            subscribers.0.remove(key);
+        }
     }
 
+    /// Call every subscriber with the given state. Subscribers may subscribe and unsubscribe
+    /// while being called. New subscribers are called from the next notification on, removed
+    /// ones are not called again.
+    ///
+    /// If a subscriber changes state, the new state is queued until every subscriber has seen
+    /// the current one. This way subscribers always see states in order, ending with the latest.
     pub(crate) fn notify(&self, state: Rc<S>) {
-        for (_, subscriber) in &self.borrow().0 {
-            subscriber.call(Rc::clone(&state));
+        struct Guard<'a, S>(&'a Mrc<Subscribers<S>>);
+        impl<S> Drop for Guard<'_, S> {
+            fn drop(&mut self) {
+                let mut subscribers = self.0.borrow_mut();
+                subscribers.1.active = false;
+                subscribers.1.depth = 0;
+                // Only left over if a subscriber panicked.
+                subscribers.1.queue.clear();
+                for key in std::mem::take(&mut subscribers.1.removed) {
+                    subscribers.0.remove(key);
+                }
+            }
+        }
+
+        {
+            let mut subscribers = self.borrow_mut();
+            let depth = subscribers.next_depth();
+            subscribers.1.queue.push_back((state, depth));
+            if subscribers.1.active {
+                // Already notifying further up the stack, it'll get to this state next.
+                return;
+            }
+            subscribers.1.active = true;
+        }
+        let _guard = Guard(self);
+
+        loop {
+            let (state, current) = {
+                let mut subscribers = self.borrow_mut();
+                let state = match subscribers.1.queue.pop_front() {
+                    Some((state, depth)) => {
+                        subscribers.1.depth = depth;
+                        state
+                    }
+                    None => break,
+                };
+                let current: Vec<_> = subscribers
+                    .0
+                    .iter()
+                    .map(|(key, subscriber)| (key, Rc::clone(subscriber)))
+                    .collect();
+
+                (state, current)
+            };
+
+            for (key, subscriber) in current {
+                if self.borrow().1.removed.contains(&key) {
+                    continue;
+                }
+
+                subscriber.call(Rc::clone(&state));
+            }
         }
     }
 }
@@ -48,13 +152,14 @@ impl<S> PartialEq for Subscribers<S> {
 
 impl<S> Default for Subscribers<S> {
     fn default() -> Self {
-        Self(Default::default())
+        Self(Default::default(), Default::default())
     }
 }
 
 /// Points to a subscriber in context. That subscriber is removed when this is dropped.
 pub struct SubscriberId<S: Store> {
     subscribers_ref: Mrc<Subscribers<S>>,
+    cx: WeakContext,
     pub(crate) key: usize,
     pub(crate) _store_type: PhantomData<S>,
 }
@@ -75,7 +180,10 @@ impl<S: Store> SubscriberId<S> {
 
 impl<S: Store> Drop for SubscriberId<S> {
     fn drop(&mut self) {
-        self.subscribers_ref.unsubscribe(self.key)
+        self.subscribers_ref.unsubscribe(self.key);
+        if let Some(cx) = self.cx.upgrade() {
+            cx.collect::<S>();
+        }
     }
 }
 
@@ -100,7 +208,7 @@ mod tests {
 
     use super::*;
 
-    use crate::context;
+    use crate::context::Context;
     use crate::dispatch::{self, Dispatch};
     use crate::mrc::Mrc;
 
@@ -118,7 +226,7 @@ mod tests {
 
     #[test]
     fn subscribe_adds_to_list() {
-        let context = context::get_or_init::<Mrc<Subscribers<TestState>>>();
+        let context = Context::global().get_or_init_internal::<Mrc<Subscribers<TestState>>>();
 
         assert!(context.store.borrow().borrow().0.is_empty());
 
@@ -129,7 +237,7 @@ mod tests {
 
     #[test]
     fn unsubscribe_removes_from_list() {
-        let context = context::get_or_init::<Mrc<Subscribers<TestState>>>();
+        let context = Context::global().get_or_init_internal::<Mrc<Subscribers<TestState>>>();
 
         assert!(context.store.borrow().borrow().0.is_empty());
 
@@ -144,7 +252,7 @@ mod tests {
 
     #[test]
     fn subscriber_id_unsubscribes_when_dropped() {
-        let context = context::get_or_init::<Mrc<Subscribers<TestState>>>();
+        let context = Context::global().get_or_init_internal::<Mrc<Subscribers<TestState>>>();
 
         assert!(context.store.borrow().borrow().0.is_empty());
 
@@ -197,4 +305,74 @@ mod tests {
 
         assert_eq!(dispatch.get().0, 1)
     }
+
+    #[test]
+    fn can_subscribe_inside_on_changed() {
+        let added = Mrc::new(Vec::new());
+        let calls = Mrc::new(0);
+        let _id = {
+            let added = added.clone();
+            let calls = calls.clone();
+            dispatch::subscribe_silent(move |_: Rc<TestState>| {
+                let calls = calls.clone();
+                let id =
+                    dispatch::subscribe_silent(move |_: Rc<TestState>| *calls.borrow_mut() += 1);
+                added.borrow_mut().push(id);
+            })
+        };
+
+        dispatch::reduce_mut(|state: &mut TestState| state.0 += 1);
+        // The new subscriber takes effect from the next change.
+        assert_eq!(*calls.borrow(), 0);
+
+        dispatch::reduce_mut(|state: &mut TestState| state.0 += 1);
+        assert_eq!(*calls.borrow(), 1);
+    }
+
+    #[test]
+    fn can_unsubscribe_inside_on_changed() {
+        let own: Mrc<Option<SubscriberId<TestState>>> = Default::default();
+        let other: Mrc<Option<SubscriberId<TestState>>> = Default::default();
+        let calls = Mrc::new(0);
+
+        *own.borrow_mut() = {
+            let own = own.clone();
+            let other = other.clone();
+            Some(dispatch::subscribe_silent(move |_: Rc<TestState>| {
+                own.borrow_mut().take();
+                other.borrow_mut().take();
+            }))
+        };
+        *other.borrow_mut() = {
+            let calls = calls.clone();
+            Some(dispatch::subscribe_silent(move |_: Rc<TestState>| {
+                *calls.borrow_mut() += 1
+            }))
+        };
+
+        dispatch::reduce_mut(|state: &mut TestState| state.0 += 1);
+
+        // Removed before its turn, so never called.
+        assert_eq!(*calls.borrow(), 0);
+        let subscribers = Context::global().get_or_init_internal::<Mrc<Subscribers<TestState>>>();
+        assert!(subscribers.store.borrow().borrow().0.is_empty());
+    }
+
+    #[test]
+    fn subscribers_see_states_in_order() {
+        let seen = Mrc::new(Vec::new());
+        let _first = dispatch::subscribe_silent(|state: Rc<TestState>| {
+            if state.0 == 1 {
+                dispatch::reduce_mut(|state: &mut TestState| state.0 += 1);
+            }
+        });
+        let _second = {
+            let seen = seen.clone();
+            dispatch::subscribe_silent(move |state: Rc<TestState>| seen.borrow_mut().push(state.0))
+        };
+
+        dispatch::reduce_mut(|state: &mut TestState| state.0 += 1);
+
+        assert_eq!(*seen.borrow(), vec![1, 2]);
+    }
 }
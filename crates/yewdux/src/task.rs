@@ -0,0 +1,576 @@
+//! Cancel async reducers, and decide what happens when they overlap.
+//!
+//! An async reducer takes its state when it starts, and writes its result back whenever it
+//! finishes. When several run at once, a slow one may overwrite the result of a newer one. Running
+//! them through [`Dispatch::with_policy`](crate::dispatch::Dispatch::with_policy) gives each a
+//! [`Task`] that can be cancelled, and applies a [`Policy`] to every reducer started with the same
+//! key.
+//!
+//! ```
+//! use std::rc::Rc;
+//!
+//! use yewdux::{prelude::*, task::Policy};
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Search {
+//!     results: Vec<String>,
+//! }
+//!
+//! async fn search(query: String) -> Vec<String> {
+//!     // Make the request here.
+//!     vec![query]
+//! }
+//!
+//! # fn main() {
+//! let dispatch = Dispatch::<Search>::new();
+//! // Only results for the most recent query are kept.
+//! let oninput = dispatch.with_policy("search", Policy::LatestWins).reduce_future_callback_with(
+//!     |_, query: String| async move {
+//!         Search {
+//!             results: search(query).await,
+//!         }
+//!         .into()
+//!     },
+//! );
+//! # }
+//! ```
+use std::{
+    any::TypeId,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    future::{poll_fn, Future},
+    marker::PhantomData,
+    pin::{pin, Pin},
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+use yew::Callback;
+
+use crate::{
+    context::Context,
+    mrc::Mrc,
+    store::{AsyncReducer, Store},
+};
+
+/// What to do when an async reducer starts while others with the same key are still running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Cancel the ones still running.
+    LatestWins,
+    /// Cancel the new one.
+    FirstWins,
+    /// Start the new one once the others are done, so it gets their result as its state.
+    Queue,
+}
+
+/// Returned when awaiting an async reducer that was cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("The task was cancelled")]
+pub struct Cancelled;
+
+#[derive(Default)]
+struct State {
+    cancelled: Cell<bool>,
+    finished: Cell<bool>,
+    wakers: RefCell<Vec<Waker>>,
+}
+
+/// A handle to a running async reducer.
+#[derive(Clone, Default)]
+pub struct Task(Rc<State>);
+
+impl Task {
+    /// Stop the reducer, if it hasn't finished yet. Its result is never applied.
+    pub fn cancel(&self) {
+        if !self.is_finished() {
+            self.0.cancelled.set(true);
+            self.wake();
+        }
+    }
+
+    /// Whether the reducer was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.get()
+    }
+
+    /// Whether the reducer is done, either applied or abandoned.
+    pub fn is_finished(&self) -> bool {
+        self.0.finished.get()
+    }
+
+    fn finish(&self) {
+        self.0.finished.set(true);
+        self.wake();
+    }
+
+    fn wake(&self) {
+        for waker in self.0.wakers.take() {
+            waker.wake();
+        }
+    }
+
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.wakers.borrow_mut();
+        if !wakers.iter().any(|other| other.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wait until this task is finished or cancelled.
+    async fn settled(&self) {
+        poll_fn(|cx| {
+            if self.is_finished() || self.is_cancelled() {
+                return Poll::Ready(());
+            }
+
+            self.register(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Run `future`, returning `None` if this task is cancelled before it is done.
+    pub(crate) async fn until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            if self.is_cancelled() {
+                return Poll::Ready(None);
+            }
+
+            if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                return Poll::Ready(Some(output));
+            }
+
+            self.register(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// An async reducer started with a [`Policy`]. Resolves once the reducer is applied, or with
+/// [`Cancelled`] if it never will be. Dropping it abandons the reducer.
+pub struct TaskFuture {
+    task: Task,
+    future: Pin<Box<dyn Future<Output = Result<(), Cancelled>>>>,
+}
+
+impl TaskFuture {
+    /// Handle to cancel the reducer.
+    pub fn task(&self) -> Task {
+        self.task.clone()
+    }
+}
+
+impl Future for TaskFuture {
+    type Output = Result<(), Cancelled>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let poll = self.future.as_mut().poll(cx);
+        if poll.is_ready() {
+            self.task.finish();
+        }
+
+        poll
+    }
+}
+
+impl Drop for TaskFuture {
+    fn drop(&mut self) {
+        // Don't hold up reducers queued after this one.
+        self.task.finish();
+    }
+}
+
+/// Tasks that may still be running, by store and key.
+#[derive(Default)]
+struct Running(HashMap<(TypeId, &'static str), Vec<Task>>);
+impl Store for Mrc<Running> {
+    fn new() -> Self {
+        Running::default().into()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+impl Context {
+    fn running(&self) -> Mrc<Running> {
+        let entry = self.get_or_init_internal::<Mrc<Running>>();
+        let running = entry.store.borrow().as_ref().clone();
+        running
+    }
+
+    /// Start an async reducer for `S`, applying `policy` to others running with the same key.
+    pub(crate) fn spawn_future<S, R>(&self, key: &'static str, policy: Policy, r: R) -> TaskFuture
+    where
+        S: Store,
+        R: AsyncReducer<S> + 'static,
+    {
+        let task = Task::default();
+        let running = self.running();
+        let mut running = running.borrow_mut();
+        let tasks = running.0.entry((TypeId::of::<S>(), key)).or_default();
+        tasks.retain(|task| !task.is_finished());
+
+        let mut earlier = Vec::new();
+        match policy {
+            Policy::LatestWins => tasks.iter().for_each(Task::cancel),
+            Policy::FirstWins if tasks.iter().any(|task| !task.is_cancelled()) => task.cancel(),
+            Policy::FirstWins => {}
+            Policy::Queue => earlier = tasks.clone(),
+        }
+        tasks.push(task.clone());
+
+        let future = {
+            let cx = self.clone();
+            let task = task.clone();
+            async move {
+                for earlier in earlier {
+                    task.until_cancelled(earlier.settled())
+                        .await
+                        .ok_or(Cancelled)?;
+                }
+
+                let applied = cx.reduce_future_task(r, Some(&task)).await;
+                if !applied && task.is_cancelled() {
+                    return Err(Cancelled);
+                }
+
+                Ok(())
+            }
+        };
+
+        TaskFuture {
+            task,
+            future: Box::pin(future),
+        }
+    }
+
+    /// Cancel every async reducer for `S` running with the given key.
+    pub(crate) fn cancel_futures<S: Store>(&self, key: &'static str) {
+        let tasks = self
+            .running()
+            .borrow_mut()
+            .0
+            .remove(&(TypeId::of::<S>(), key))
+            .unwrap_or_default();
+        for task in tasks {
+            task.cancel();
+        }
+    }
+}
+
+/// Runs async reducers with a [`Policy`]. See [`Dispatch::with_policy`].
+///
+/// [`Dispatch::with_policy`]: crate::dispatch::Dispatch::with_policy
+pub struct PolicyDispatch<S> {
+    cx: Context,
+    key: &'static str,
+    policy: Policy,
+    _marker: PhantomData<S>,
+}
+
+impl<S: Store> PolicyDispatch<S> {
+    pub(crate) fn new(cx: &Context, key: &'static str, policy: Policy) -> Self {
+        Self {
+            cx: cx.clone(),
+            key,
+            policy,
+            _marker: Default::default(),
+        }
+    }
+
+    /// Apply an [`AsyncReducer`].
+    pub fn apply_future<R: AsyncReducer<S> + 'static>(&self, reducer: R) -> TaskFuture {
+        self.cx.spawn_future(self.key, self.policy, reducer)
+    }
+
+    /// Change state from a future.
+    pub fn reduce_future<FUT, FUN>(&self, f: FUN) -> TaskFuture
+    where
+        FUT: Future<Output = Rc<S>> + 'static,
+        FUN: FnOnce(Rc<S>) -> FUT + 'static,
+    {
+        self.cx.spawn_future(self.key, self.policy, f)
+    }
+
+    /// Create a callback for applying an [`AsyncReducer`].
+    pub fn apply_future_callback<E, M, F>(&self, f: F) -> Callback<E>
+    where
+        M: AsyncReducer<S> + 'static,
+        F: Fn(E) -> M + 'static,
+    {
+        let (cx, key, policy) = (self.cx.clone(), self.key, self.policy);
+        Callback::from(move |e| {
+            let future = cx.spawn_future(key, policy, f(e));
+            yew::platform::spawn_local(async move {
+                let _ = future.await;
+            })
+        })
+    }
+
+    /// Create a callback that changes state from a future.
+    pub fn reduce_future_callback<FUT, FUN, E>(&self, f: FUN) -> Callback<E>
+    where
+        FUT: Future<Output = Rc<S>> + 'static,
+        FUN: Fn(Rc<S>) -> FUT + 'static,
+        E: 'static,
+    {
+        let f = Rc::new(f);
+        self.reduce_future_callback_with(move |state, _: E| f(state))
+    }
+
+    /// Create a callback that changes state from a future, with the fired event.
+    pub fn reduce_future_callback_with<FUT, FUN, E>(&self, f: FUN) -> Callback<E>
+    where
+        FUT: Future<Output = Rc<S>> + 'static,
+        FUN: Fn(Rc<S>, E) -> FUT + 'static,
+        E: 'static,
+    {
+        let (cx, key, policy) = (self.cx.clone(), self.key, self.policy);
+        let f = Rc::new(f);
+        Callback::from(move |e: E| {
+            let f = f.clone();
+            let future = cx.spawn_future(key, policy, move |state| f(state, e));
+            yew::platform::spawn_local(async move {
+                let _ = future.await;
+            })
+        })
+    }
+
+    /// Mutate state from a future.
+    pub fn reduce_mut_future<R, F>(&self, f: F) -> TaskFuture
+    where
+        S: Clone,
+        R: 'static,
+        F: FnOnce(&mut S) -> Pin<Box<dyn Future<Output = R> + '_>> + 'static,
+    {
+        self.reduce_future(|mut state| async move {
+            f(Rc::make_mut(&mut state)).await;
+            state
+        })
+    }
+
+    /// Create a callback that mutates state from a future.
+    pub fn reduce_mut_future_callback<R, F, E>(&self, f: F) -> Callback<E>
+    where
+        S: Clone,
+        R: 'static,
+        F: Fn(&mut S) -> Pin<Box<dyn Future<Output = R> + '_>> + 'static,
+        E: 'static,
+    {
+        let f = Rc::new(f);
+        self.reduce_mut_future_callback_with(move |state, _: E| f(state))
+    }
+
+    /// Create a callback that mutates state from a future, with the fired event.
+    pub fn reduce_mut_future_callback_with<R, F, E>(&self, f: F) -> Callback<E>
+    where
+        S: Clone,
+        R: 'static,
+        F: Fn(&mut S, E) -> Pin<Box<dyn Future<Output = R> + '_>> + 'static,
+        E: 'static,
+    {
+        let (cx, key, policy) = (self.cx.clone(), self.key, self.policy);
+        let f = Rc::new(f);
+        Callback::from(move |e: E| {
+            let f = f.clone();
+            let future = PolicyDispatch::<S>::new(&cx, key, policy)
+                .reduce_mut_future(move |state| f(state, e));
+            yew::platform::spawn_local(async move {
+                let _ = future.await;
+            })
+        })
+    }
+
+    /// Cancel every async reducer running with this key.
+    pub fn cancel(&self) {
+        self.cx.cancel_futures::<S>(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dispatch::Dispatch,
+        test_util::{isolated, TestState},
+    };
+
+    use super::*;
+
+    /// A future that is ready once opened.
+    #[derive(Clone, Default)]
+    struct Gate(Rc<Cell<bool>>);
+
+    impl Gate {
+        fn open(&self) {
+            self.0.set(true);
+        }
+
+        async fn wait(self) {
+            poll_fn(|_| match self.0.get() {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            })
+            .await
+        }
+    }
+
+    /// Adds `n` to the state it started with, once `gate` is open.
+    fn add(gate: &Gate, n: u32) -> impl AsyncReducer<TestState> {
+        let gate = gate.clone();
+        move |state: Rc<TestState>| async move {
+            gate.wait().await;
+            TestState(state.0 + n).into()
+        }
+    }
+
+    /// Like [`add`], but mutates the state in place.
+    fn add_mut(
+        gate: &Gate,
+        n: u32,
+    ) -> impl FnOnce(&mut TestState) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+        let gate = gate.clone();
+        move |state| {
+            Box::pin(async move {
+                gate.wait().await;
+                state.0 += n;
+            })
+        }
+    }
+
+    fn poll(future: &mut TaskFuture) -> Poll<Result<(), Cancelled>> {
+        Pin::new(future).poll(&mut std::task::Context::from_waker(Waker::noop()))
+    }
+
+    fn setup(policy: Policy) -> (Dispatch<TestState>, PolicyDispatch<TestState>) {
+        let dispatch = isolated::<TestState>();
+        let tasks = dispatch.with_policy("add", policy);
+
+        (dispatch, tasks)
+    }
+
+    #[test]
+    fn latest_wins() {
+        let (dispatch, tasks) = setup(Policy::LatestWins);
+        let (a, b) = (Gate::default(), Gate::default());
+
+        let mut first = tasks.apply_future(add(&a, 1));
+        assert!(poll(&mut first).is_pending());
+        let mut second = tasks.apply_future(add(&b, 2));
+        assert!(poll(&mut second).is_pending());
+
+        a.open();
+        assert_eq!(poll(&mut first), Poll::Ready(Err(Cancelled)));
+        assert!(dispatch.get().0 == 0);
+
+        b.open();
+        assert_eq!(poll(&mut second), Poll::Ready(Ok(())));
+        assert!(dispatch.get().0 == 2);
+    }
+
+    #[test]
+    fn latest_wins_mut() {
+        let (dispatch, tasks) = setup(Policy::LatestWins);
+        let (a, b) = (Gate::default(), Gate::default());
+
+        let mut first = tasks.reduce_mut_future(add_mut(&a, 1));
+        assert!(poll(&mut first).is_pending());
+        let mut second = tasks.reduce_mut_future(add_mut(&b, 2));
+        assert!(poll(&mut second).is_pending());
+
+        a.open();
+        assert_eq!(poll(&mut first), Poll::Ready(Err(Cancelled)));
+        assert!(dispatch.get().0 == 0);
+
+        b.open();
+        assert_eq!(poll(&mut second), Poll::Ready(Ok(())));
+        assert!(dispatch.get().0 == 2);
+    }
+
+    #[test]
+    fn first_wins() {
+        let (dispatch, tasks) = setup(Policy::FirstWins);
+        let gate = Gate::default();
+
+        let mut first = tasks.apply_future(add(&gate, 1));
+        assert!(poll(&mut first).is_pending());
+        let mut second = tasks.apply_future(add(&gate, 2));
+        assert_eq!(poll(&mut second), Poll::Ready(Err(Cancelled)));
+
+        gate.open();
+        assert_eq!(poll(&mut first), Poll::Ready(Ok(())));
+        assert!(dispatch.get().0 == 1);
+
+        // Nothing is running anymore.
+        let mut third = tasks.apply_future(add(&gate, 2));
+        assert_eq!(poll(&mut third), Poll::Ready(Ok(())));
+        assert!(dispatch.get().0 == 3);
+    }
+
+    #[test]
+    fn queue() {
+        let (dispatch, tasks) = setup(Policy::Queue);
+        let gate = Gate::default();
+
+        let mut first = tasks.apply_future(add(&gate, 1));
+        let mut second = tasks.apply_future(add(&gate, 2));
+        gate.open();
+
+        assert!(poll(&mut second).is_pending());
+        assert_eq!(poll(&mut first), Poll::Ready(Ok(())));
+        assert_eq!(poll(&mut second), Poll::Ready(Ok(())));
+        // The second started with the result of the first.
+        assert!(dispatch.get().0 == 3);
+    }
+
+    #[test]
+    fn task_can_be_cancelled() {
+        let (dispatch, tasks) = setup(Policy::Queue);
+        let gate = Gate::default();
+
+        let mut future = tasks.apply_future(add(&gate, 1));
+        assert!(poll(&mut future).is_pending());
+        future.task().cancel();
+        gate.open();
+
+        assert_eq!(poll(&mut future), Poll::Ready(Err(Cancelled)));
+        assert!(future.task().is_cancelled());
+        assert!(dispatch.get().0 == 0);
+    }
+
+    #[test]
+    fn keys_are_independent() {
+        let (dispatch, tasks) = setup(Policy::LatestWins);
+        let other = dispatch.with_policy("other", Policy::LatestWins);
+        let gate = Gate::default();
+
+        let mut first = tasks.apply_future(add(&gate, 1));
+        assert!(poll(&mut first).is_pending());
+        let mut second = other.apply_future(add(&gate, 2));
+        assert!(poll(&mut second).is_pending());
+        gate.open();
+
+        assert_eq!(poll(&mut first), Poll::Ready(Ok(())));
+        assert_eq!(poll(&mut second), Poll::Ready(Ok(())));
+        assert!(dispatch.get().0 == 2);
+    }
+
+    #[test]
+    fn all_tasks_can_be_cancelled() {
+        let (dispatch, tasks) = setup(Policy::Queue);
+        let gate = Gate::default();
+
+        let mut first = tasks.apply_future(add(&gate, 1));
+        let mut second = tasks.apply_future(add(&gate, 2));
+        tasks.cancel();
+        gate.open();
+
+        assert_eq!(poll(&mut first), Poll::Ready(Err(Cancelled)));
+        assert_eq!(poll(&mut second), Poll::Ready(Err(Cancelled)));
+        assert!(dispatch.get().0 == 0);
+    }
+}
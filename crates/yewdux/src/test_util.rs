@@ -0,0 +1,51 @@
+//! Fixtures shared by unit tests.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    context::Context,
+    dispatch::Dispatch,
+    store::{Named, Store},
+};
+
+/// A store holding a number, starting at 0.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TestState(pub(crate) u32);
+
+impl Store for TestState {
+    fn new() -> Self {
+        Self(0)
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+impl Named for TestState {
+    const NAME: &'static str = "TestState";
+}
+
+/// Declare more stores like [`TestState`], each starting at the given number, for tests that need
+/// several.
+macro_rules! number_stores {
+    ($($name:ident = $initial:expr),* $(,)?) => {$(
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        struct $name(u32);
+
+        impl $crate::store::Store for $name {
+            fn new() -> Self {
+                Self($initial)
+            }
+
+            fn should_notify(&self, other: &Self) -> bool {
+                self != other
+            }
+        }
+    )*};
+}
+pub(crate) use number_stores;
+
+/// A dispatch for `S` in a new context, so state isn't shared with other tests.
+pub(crate) fn isolated<S: Store>() -> Dispatch<S> {
+    Dispatch::with_context(&Context::new())
+}
@@ -0,0 +1,241 @@
+//! Change several stores at once, or not at all.
+//!
+//! ```
+//! use yewdux::prelude::*;
+//!
+//! #[derive(Default, Clone, PartialEq, Store)]
+//! struct Cart {
+//!     items: Vec<u32>,
+//! }
+//!
+//! #[derive(Clone, PartialEq, Store)]
+//! struct Inventory {
+//!     stock: u32,
+//! }
+//!
+//! impl Default for Inventory {
+//!     fn default() -> Self {
+//!         Self { stock: 1 }
+//!     }
+//! }
+//!
+//! fn buy(item: u32) -> Result<(), &'static str> {
+//!     Dispatch::<Cart>::new().transaction(|tx| {
+//!         tx.reduce_mut(|cart: &mut Cart| cart.items.push(item));
+//!         tx.try_reduce_mut(|inventory: &mut Inventory| {
+//!             inventory.stock = inventory.stock.checked_sub(1).ok_or("out of stock")?;
+//!             Ok(())
+//!         })
+//!     })
+//! }
+//!
+//! # fn main() {
+//! assert!(buy(1).is_ok());
+//! // The cart is left untouched when the item is out of stock.
+//! assert!(buy(2).is_err());
+//! assert!(Dispatch::<Cart>::new().get().items == vec![1]);
+//! # }
+//! ```
+use std::{any::TypeId, cell::RefCell, rc::Rc};
+
+use crate::{context::Context, effect::EffectReducer, store::Store};
+
+type Restore = Box<dyn FnOnce()>;
+
+/// Stages changes to any number of stores. See [`Dispatch::transaction`].
+///
+/// [`Dispatch::transaction`]: crate::dispatch::Dispatch::transaction
+pub struct Transaction {
+    cx: Context,
+    /// Restores every changed store to its state from before the transaction.
+    rollback: RefCell<Vec<(TypeId, Restore)>>,
+}
+
+impl Transaction {
+    fn new(cx: &Context) -> Self {
+        Self {
+            cx: cx.clone(),
+            rollback: Default::default(),
+        }
+    }
+
+    /// Remember the current state of `S`, if it hasn't been already.
+    fn stage<S: Store>(&self) {
+        let mut rollback = self.rollback.borrow_mut();
+        if rollback.iter().any(|(id, _)| *id == TypeId::of::<S>()) {
+            return;
+        }
+
+        let entry = self.cx.get_or_init::<S>();
+        let old = Rc::clone(&entry.store.borrow());
+        rollback.push((
+            TypeId::of::<S>(),
+            Box::new(move || *entry.store.borrow_mut() = old),
+        ));
+    }
+
+    /// Get the current state, including changes made so far.
+    pub fn get<S: Store>(&self) -> Rc<S> {
+        self.cx.get::<S>()
+    }
+
+    /// Change state from a function.
+    pub fn reduce<S: Store, F: FnOnce(Rc<S>) -> Rc<S>>(&self, f: F) {
+        self.stage::<S>();
+        self.cx.reduce(f);
+    }
+
+    /// Change state using a mutable reference from a function.
+    pub fn reduce_mut<S: Store + Clone, F: FnOnce(&mut S)>(&self, f: F) {
+        self.stage::<S>();
+        self.cx.reduce_mut(f);
+    }
+
+    /// Change state from a function that returns effects. The effects only run if the transaction
+    /// succeeds.
+    pub fn apply_effect<S: Store, R: EffectReducer<S>>(&self, reducer: R) {
+        self.stage::<S>();
+        self.cx.reduce_effect(reducer);
+    }
+
+    /// Change state from a function that may fail. On error, state is left untouched and the
+    /// error is returned, so it can be propagated to abort the transaction.
+    pub fn try_reduce<S, E, F>(&self, f: F) -> Result<(), E>
+    where
+        S: Store,
+        F: FnOnce(Rc<S>) -> Result<Rc<S>, E>,
+    {
+        let new = f(self.get::<S>())?;
+        self.reduce(|_| new);
+
+        Ok(())
+    }
+
+    /// Change state using a mutable reference from a function that may fail. On error, state is
+    /// left untouched and the error is returned, so it can be propagated to abort the
+    /// transaction.
+    pub fn try_reduce_mut<S, E, F>(&self, f: F) -> Result<(), E>
+    where
+        S: Store + Clone,
+        F: FnOnce(&mut S) -> Result<(), E>,
+    {
+        self.try_reduce(|mut state: Rc<S>| {
+            f(Rc::make_mut(&mut state))?;
+            Ok(state)
+        })
+    }
+}
+
+impl Context {
+    /// Run `f` as a transaction. If it returns an error every store it changed is restored, and
+    /// no subscribers are notified. Otherwise subscribers of changed stores are notified once, when
+    /// the transaction is done.
+    pub(crate) fn transaction<F, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&Transaction) -> Result<T, E>,
+    {
+        self.batch(|| {
+            let pending = self.pending_len();
+            let intercepted = self.intercepted_len();
+            let tx = Transaction::new(self);
+
+            let result = f(&tx);
+            if result.is_err() {
+                for (_, restore) in tx.rollback.take().into_iter().rev() {
+                    restore();
+                }
+                // Stores changed before this transaction may still need to notify.
+                self.discard_pending(pending);
+                self.discard_intercepted(intercepted);
+            }
+
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{dispatch, mrc::Mrc, subscriber::SubscriberId, test_util::number_stores};
+
+    use super::*;
+
+    number_stores!(Cart = 0, Inventory = 1);
+
+    fn take(inventory: &mut Inventory) -> Result<(), &'static str> {
+        inventory.0 = inventory.0.checked_sub(1).ok_or("out of stock")?;
+        Ok(())
+    }
+
+    type Ids = (SubscriberId<Cart>, SubscriberId<Inventory>);
+
+    fn watch() -> (Mrc<Vec<&'static str>>, Ids) {
+        let seen = Mrc::new(Vec::new());
+        let cart = {
+            let seen = seen.clone();
+            dispatch::subscribe_silent(move |_: Rc<Cart>| seen.borrow_mut().push("cart"))
+        };
+        let inventory = {
+            let seen = seen.clone();
+            dispatch::subscribe_silent(move |_: Rc<Inventory>| seen.borrow_mut().push("inventory"))
+        };
+
+        (seen, (cart, inventory))
+    }
+
+    #[test]
+    fn commit_applies_all_and_notifies_once() {
+        let (seen, _ids) = watch();
+
+        let result = Context::global().transaction(|tx| {
+            tx.reduce_mut(|cart: &mut Cart| cart.0 += 1);
+            tx.try_reduce_mut(take)?;
+            tx.reduce_mut(|cart: &mut Cart| cart.0 += 1);
+
+            assert!(seen.borrow().is_empty());
+            Ok::<_, &str>(())
+        });
+
+        assert!(result.is_ok());
+        assert!(dispatch::get::<Cart>().0 == 2);
+        assert!(dispatch::get::<Inventory>().0 == 0);
+        assert_eq!(*seen.borrow(), vec!["cart", "inventory"]);
+    }
+
+    #[test]
+    fn error_rolls_back_all_stores() {
+        dispatch::set(Inventory(0));
+        let cart = dispatch::get::<Cart>();
+        let (seen, _ids) = watch();
+
+        let result = Context::global().transaction(|tx| {
+            tx.reduce_mut(|cart: &mut Cart| cart.0 += 1);
+            tx.try_reduce_mut(take)
+        });
+
+        assert_eq!(result, Err("out of stock"));
+        assert!(Rc::ptr_eq(&dispatch::get::<Cart>(), &cart));
+        assert!(seen.borrow().is_empty());
+    }
+
+    #[test]
+    fn rollback_keeps_changes_from_enclosing_batch() {
+        let (seen, _ids) = watch();
+
+        dispatch::batch(|| {
+            dispatch::reduce_mut(|cart: &mut Cart| cart.0 += 1);
+
+            let result = Context::global().transaction(|tx| {
+                tx.reduce_mut(|cart: &mut Cart| cart.0 += 1);
+                tx.reduce_mut(|inventory: &mut Inventory| inventory.0 += 1);
+                Err::<(), _>("abort")
+            });
+
+            assert!(result.is_err());
+        });
+
+        assert!(dispatch::get::<Cart>().0 == 1);
+        assert!(dispatch::get::<Inventory>().0 == 1);
+        assert_eq!(*seen.borrow(), vec!["cart"]);
+    }
+}
@@ -0,0 +1,97 @@
+use yewdux::{
+    fields::{FieldChanges, FieldMask},
+    prelude::*,
+};
+
+#[derive(Default, Clone, PartialEq, Store)]
+#[store(fields)]
+struct User {
+    name: String,
+    email: String,
+    visits: u32,
+}
+
+#[test]
+fn changed_fields_are_found() {
+    let old = User::default();
+    let new = User {
+        name: "Jane".into(),
+        visits: 1,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        new.changed_fields(&old),
+        User::FIELDS.name | User::FIELDS.visits
+    );
+    assert_eq!(new.changed_fields(&new), FieldMask::NONE);
+}
+
+#[test]
+fn fields_have_one_mask_each() {
+    let fields: UserFields = User::FIELDS;
+
+    assert_eq!(fields.name, FieldMask::field(0));
+    assert_eq!(fields.email, FieldMask::field(1));
+    assert_eq!(fields.visits, FieldMask::field(2));
+}
+
+#[derive(Default, Clone, PartialEq, Store)]
+#[store(fields)]
+struct Page<T: Default + Clone + PartialEq + 'static> {
+    rows: Vec<T>,
+    selected: Option<usize>,
+}
+
+#[test]
+fn generic_stores_find_changed_fields() {
+    let old = Page::<String>::default();
+    let new = Page {
+        selected: Some(0),
+        ..old.clone()
+    };
+
+    assert_eq!(new.changed_fields(&old), Page::<String>::FIELDS.selected);
+    // The masks don't depend on the type parameter.
+    let _: PageFields = Page::<u32>::FIELDS;
+}
+
+/// Taken, so the store below can't use the default name.
+#[allow(dead_code)]
+struct SettingsFields;
+
+#[derive(Default, Clone, PartialEq, Store)]
+#[store(fields, fields_name = SettingsMasks)]
+struct Settings {
+    theme: String,
+}
+
+#[test]
+fn fields_type_can_be_renamed() {
+    let masks: SettingsMasks = Settings::FIELDS;
+    let new = Settings {
+        theme: "dark".into(),
+    };
+
+    assert_eq!(new.changed_fields(&Settings::default()), masks.theme);
+}
+
+#[test]
+fn subscribers_are_only_notified_of_their_fields() {
+    use std::{cell::Cell, rc::Rc};
+
+    let cx = yewdux::Context::new();
+    let notified = Rc::new(Cell::new(0));
+    let dispatch = {
+        let notified = notified.clone();
+        Dispatch::<User>::subscribe_fields_silent_with_context(&cx, User::FIELDS.name, move |_| {
+            notified.set(notified.get() + 1)
+        })
+    };
+
+    dispatch.reduce_mut(|user| user.visits += 1);
+    assert_eq!(notified.get(), 0);
+
+    dispatch.reduce_mut(|user| user.name = "Jane".into());
+    assert_eq!(notified.get(), 1);
+}
@@ -0,0 +1,252 @@
+use std::{rc::Rc, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use yew::{platform::Runtime, prelude::*, suspense::use_future, ServerRenderer};
+use yewdux::{hydration::HydrationScript, prelude::*};
+
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize, Store)]
+#[store(hydrate)]
+struct Session {
+    user: String,
+}
+
+#[derive(Properties, PartialEq, Clone)]
+struct AppProps {
+    user: String,
+}
+
+/// Writes the request's user to the store while rendering.
+#[function_component]
+fn Login(props: &AppProps) -> Html {
+    let dispatch = use_dispatch::<Session>();
+    let user = props.user.clone();
+    use_memo((), move |_| dispatch.reduce_mut(|session| session.user = user));
+
+    html! {}
+}
+
+/// Suspends for a while, giving the other request a chance to render.
+#[function_component]
+fn Greeting() -> HtmlResult {
+    use_future(|| yew::platform::time::sleep(Duration::from_millis(50)))?;
+    let session = use_store_value::<Session>();
+
+    Ok(html! {
+        <p>{ format!("Hello, {}!", session.user) }</p>
+    })
+}
+
+#[function_component]
+fn App(props: &AppProps) -> Html {
+    html! {
+        <YewduxRoot>
+            <Login user={props.user.clone()} />
+            <Suspense>
+                <Greeting />
+            </Suspense>
+            <HydrationScript />
+        </YewduxRoot>
+    }
+}
+
+async fn render(rt: Runtime, user: &'static str) -> String {
+    ServerRenderer::<App>::with_props(move || AppProps { user: user.into() })
+        .with_runtime(rt)
+        .hydratable(false)
+        .render()
+        .await
+}
+
+#[async_std::test]
+async fn concurrent_renders_do_not_share_state() {
+    // A single worker thread, so both renders share the same thread locals.
+    let rt = Runtime::builder().worker_threads(1).build().unwrap();
+
+    let alice = async_std::task::spawn(render(rt.clone(), "alice"));
+    let bob = async_std::task::spawn(render(rt.clone(), "bob"));
+    let (alice, bob) = (alice.await, bob.await);
+    // The runtime shuts down once every handle is dropped, so keep it alive until rendering is
+    // done.
+    drop(rt);
+
+    assert!(alice.contains("Hello, alice!"), "{alice}");
+    assert!(bob.contains("Hello, bob!"), "{bob}");
+}
+
+#[async_std::test]
+async fn rendered_state_is_embedded_for_hydration() {
+    let rt = Runtime::builder().worker_threads(1).build().unwrap();
+
+    let html = render(rt.clone(), "alice").await;
+    drop(rt);
+
+    assert!(
+        html.contains(r#"<script type="application/json" id="__yewdux_state">"#),
+        "{html}"
+    );
+    assert!(html.contains(r#""Session":{"user":"alice"}"#), "{html}");
+}
+
+#[derive(Default, Clone, PartialEq)]
+struct Settings {
+    theme: String,
+}
+
+impl Store for Settings {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn should_notify(&self, other: &Self) -> bool {
+        self != other
+    }
+}
+
+#[cfg(feature = "future")]
+#[yewdux::async_trait(?Send)]
+impl AsyncStore for Settings {
+    type Error = String;
+
+    async fn init() -> Result<Self, Self::Error> {
+        yew::platform::time::sleep(Duration::from_millis(10)).await;
+        Ok(Self {
+            theme: "dark".into(),
+        })
+    }
+}
+
+#[cfg(feature = "future")]
+#[function_component]
+fn Theme() -> HtmlResult {
+    let (settings, _) = use_store_suspense::<Settings>()?.unwrap();
+
+    Ok(html! {
+        <p>{ format!("Theme: {}", settings.theme) }</p>
+    })
+}
+
+#[cfg(feature = "future")]
+#[function_component]
+fn Themed() -> Html {
+    html! {
+        <YewduxRoot>
+            <Suspense>
+                <Theme />
+                <Theme />
+            </Suspense>
+        </YewduxRoot>
+    }
+}
+
+#[cfg(feature = "future")]
+#[async_std::test]
+async fn suspends_until_async_store_is_loaded() {
+    let rt = Runtime::builder().worker_threads(1).build().unwrap();
+
+    let html = ServerRenderer::<Themed>::new()
+        .with_runtime(rt.clone())
+        .hydratable(false)
+        .render()
+        .await;
+    drop(rt);
+
+    assert_eq!(html.matches("Theme: dark").count(), 2, "{html}");
+}
+
+/// Greets the user with their theme, from two stores.
+#[function_component]
+fn ThemedGreeting() -> Html {
+    let greeting = use_multi_selector::<(Session, Settings), _, _>(
+        |session: &Session, settings: &Settings| {
+            format!("Hello, {} ({})!", session.user, settings.theme)
+        },
+    );
+
+    html! {
+        <p>{ greeting }</p>
+    }
+}
+
+#[function_component]
+fn MultiStore(props: &AppProps) -> Html {
+    html! {
+        <YewduxRoot>
+            <Login user={props.user.clone()} />
+            <ThemedGreeting />
+        </YewduxRoot>
+    }
+}
+
+#[async_std::test]
+async fn selects_from_multiple_stores() {
+    let rt = Runtime::builder().worker_threads(1).build().unwrap();
+
+    let html = ServerRenderer::<MultiStore>::with_props(|| AppProps {
+        user: "alice".into(),
+    })
+    .with_runtime(rt.clone())
+    .hydratable(false)
+    .render()
+    .await;
+    drop(rt);
+
+    assert!(html.contains("Hello, alice ()!"), "{html}");
+}
+
+/// Users seen by `Audit`, from every render.
+static AUDITED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Audit;
+impl Listener for Audit {
+    type Store = Visitor;
+
+    fn on_change(&mut self, state: Rc<Visitor>) {
+        AUDITED.lock().unwrap().push(state.user.clone());
+    }
+}
+
+#[derive(Default, Clone, PartialEq, Store)]
+#[store(listener(Audit))]
+struct Visitor {
+    user: String,
+}
+
+#[function_component]
+fn Visit(props: &AppProps) -> Html {
+    let dispatch = use_dispatch::<Visitor>();
+    let user = props.user.clone();
+    use_memo((), move |_| dispatch.reduce_mut(|visitor| visitor.user = user));
+
+    html! {}
+}
+
+#[function_component]
+fn Audited(props: &AppProps) -> Html {
+    html! {
+        <YewduxRoot>
+            <Visit user={props.user.clone()} />
+        </YewduxRoot>
+    }
+}
+
+async fn render_audited(rt: Runtime, user: &'static str) -> String {
+    ServerRenderer::<Audited>::with_props(move || AppProps { user: user.into() })
+        .with_runtime(rt)
+        .hydratable(false)
+        .render()
+        .await
+}
+
+#[async_std::test]
+async fn listeners_run_in_render_context() {
+    let rt = Runtime::builder().worker_threads(1).build().unwrap();
+
+    let alice = async_std::task::spawn(render_audited(rt.clone(), "alice"));
+    let bob = async_std::task::spawn(render_audited(rt.clone(), "bob"));
+    let _ = (alice.await, bob.await);
+    drop(rt);
+
+    let mut audited = AUDITED.lock().unwrap().clone();
+    audited.sort();
+    assert_eq!(audited, ["alice", "bob"]);
+}